@@ -0,0 +1,180 @@
+// Chomp Task Runner
+// Copyright (C) 2022  Guy Bedford
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::chompfile::ChompEngine;
+use crate::http_client;
+use crate::http_client::hash;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine as _};
+use hyper::Uri;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use uuid::Uuid;
+
+// Content-addressed task artifacts, shared between all projects of the user.
+// Each entry is a folder named by the artifact key containing the task targets
+// as files named by their target index.
+fn artifact_cache_dir() -> Result<PathBuf> {
+    let mut path = http_client::chomp_cache_dir()?;
+    path.push("artifacts");
+    Ok(path)
+}
+
+// the task cwd is as written in the Chompfile, so that keys are shared between checkouts
+pub fn artifact_key(
+    run: &str,
+    env: &BTreeMap<String, String>,
+    engine: ChompEngine,
+    cwd: Option<&str>,
+    dep_hashes: &BTreeMap<String, String>,
+) -> String {
+    let inputs = json!({
+        "run": run,
+        "env": env,
+        "engine": engine,
+        "cwd": cwd,
+        "deps": dep_hashes,
+    });
+    hash(inputs.to_string().as_bytes())
+}
+
+// Copies the cached targets into place, returning false on a cache miss
+pub fn restore_artifacts(key: &str, targets: &[String]) -> Result<bool> {
+    let mut dir = artifact_cache_dir()?;
+    dir.push(key);
+    if !dir.is_dir() {
+        return Ok(false);
+    }
+    for idx in 0..targets.len() {
+        if !dir.join(idx.to_string()).is_file() {
+            return Ok(false);
+        }
+    }
+    for (idx, target) in targets.iter().enumerate() {
+        if let Some(parent) = Path::new(target).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(dir.join(idx.to_string()), target)?;
+    }
+    Ok(true)
+}
+
+pub fn store_artifacts(key: &str, targets: &[String]) -> Result<()> {
+    // only file targets can be cached
+    if !targets.iter().all(|target| Path::new(target).is_file()) {
+        return Ok(());
+    }
+    let mut dir = artifact_cache_dir()?;
+    dir.push(key);
+    if dir.is_dir() {
+        return Ok(());
+    }
+    // write into a temporary folder first so that concurrent runs never observe
+    // a partial entry
+    let mut tmp_dir = artifact_cache_dir()?;
+    tmp_dir.push(format!("{}.{}", key, Uuid::new_v4().as_simple()));
+    fs::create_dir_all(&tmp_dir)?;
+    for (idx, target) in targets.iter().enumerate() {
+        fs::copy(target, tmp_dir.join(idx.to_string()))?;
+    }
+    if fs::rename(&tmp_dir, &dir).is_err() {
+        // another run stored the same entry first
+        fs::remove_dir_all(&tmp_dir)?;
+    }
+    Ok(())
+}
+
+// The remote cache protocol is a plain HTTP GET / PUT of the artifact bundle at
+// `[remote]/[key]`, with a 404 indicating a cache miss.
+#[derive(Serialize, Deserialize)]
+struct ArtifactBundle {
+    targets: Vec<String>,
+}
+
+// an unresponsive cache server is treated as a cache miss instead of stalling the build
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn remote_uri(remote: &str, key: &str) -> Result<Uri> {
+    let uri_str = format!("{}/{}", remote.trim_end_matches('/'), key);
+    uri_str
+        .parse::<Uri>()
+        .map_err(|e| anyhow!("Invalid remote cache URL {}: {}", uri_str, e))
+}
+
+// Fetches the targets from the remote cache, also populating the local cache on a hit
+pub async fn restore_remote_artifacts(
+    remote: String,
+    key: String,
+    targets: Vec<String>,
+) -> Result<bool> {
+    let uri = remote_uri(&remote, &key)?;
+    let body = match timeout(REMOTE_TIMEOUT, http_client::get(uri)).await {
+        Ok(result) => match result? {
+            Some(body) => body,
+            None => return Ok(false),
+        },
+        Err(_) => {
+            eprintln!("Remote cache request for {} timed out", key);
+            return Ok(false);
+        }
+    };
+    let bundle: ArtifactBundle = serde_json::from_slice(&body)?;
+    if bundle.targets.len() != targets.len() {
+        return Ok(false);
+    }
+    for (target, source) in targets.iter().zip(bundle.targets.iter()) {
+        if let Some(parent) = Path::new(target).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(target, general_purpose::STANDARD.decode(source)?)?;
+    }
+    store_artifacts(&key, &targets)?;
+    Ok(true)
+}
+
+pub fn upload_artifacts(
+    remote: &str,
+    key: &str,
+    targets: &[String],
+) -> Result<Option<JoinHandle<()>>> {
+    if !targets.iter().all(|target| Path::new(target).is_file()) {
+        return Ok(None);
+    }
+    let uri = remote_uri(remote, key)?;
+    let mut bundle = ArtifactBundle {
+        targets: Vec::new(),
+    };
+    for target in targets {
+        bundle
+            .targets
+            .push(general_purpose::STANDARD.encode(fs::read(target)?));
+    }
+    let body = serde_json::to_vec(&bundle)?;
+    let key = key.to_string();
+    Ok(Some(tokio::spawn(async move {
+        match timeout(REMOTE_TIMEOUT, http_client::put(uri, body)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => eprintln!("Unable to upload to the remote cache: {}", e),
+            Err(_) => eprintln!("Remote cache upload for {} timed out", key),
+        }
+    })))
+}
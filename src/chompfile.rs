@@ -14,6 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use crate::http_client::hash;
 use anyhow::Result;
 use directories::UserDirs;
 use regex::{Captures, Regex};
@@ -58,6 +59,16 @@ pub enum TaskStdio {
     None,
 }
 
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, Hash, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+#[derive(Default)]
+pub enum TaskOutput {
+    #[default]
+    Inherit,
+    Prefixed,
+    Grouped,
+}
+
 
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -74,8 +85,14 @@ pub struct Chompfile {
     #[serde(default, skip_serializing_if = "is_default")]
     pub env_default: HashMap<String, String>,
     #[serde(default, skip_serializing_if = "is_default")]
+    pub env_file: Vec<String>,
+    #[serde(default, skip_serializing_if = "is_default")]
     pub server: ServerOptions,
     #[serde(default, skip_serializing_if = "is_default")]
+    pub cache: CacheOptions,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub resources: HashMap<String, usize>,
+    #[serde(default, skip_serializing_if = "is_default")]
     pub task: Vec<ChompTaskMaybeTemplated>,
     #[serde(default, skip_serializing_if = "is_default")]
     pub template_options: HashMap<String, HashMap<String, toml::value::Value>>,
@@ -106,6 +123,15 @@ impl Default for ServerOptions {
     }
 }
 
+#[derive(Debug, Serialize, PartialEq, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CacheOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub remote_write: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
 #[serde(rename_all = "kebab-case")]
 #[derive(Default)]
@@ -113,6 +139,7 @@ pub enum InvalidationCheck {
     NotFound,
     #[default]
     Mtime,
+    Hash,
     Always,
 }
 
@@ -140,6 +167,30 @@ pub enum WatchInvalidation {
 }
 
 
+// Readiness conditions of a service task, all of which must be met
+#[derive(Debug, Serialize, PartialEq, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ServiceReady {
+    // regular expression matched against each line of the service output
+    pub log: Option<String>,
+    // local TCP port accepting connections
+    pub port: Option<u16>,
+    // file path to exist
+    pub file: Option<String>,
+}
+
+// Conditions for a task to run, all of which must be met
+#[derive(Debug, Serialize, PartialEq, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TaskCondition {
+    // environment variable to be set to a non-empty value
+    pub env: Option<String>,
+    // file path to exist
+    pub file: Option<String>,
+    // host operating system, as in "linux", "macos" or "windows"
+    pub os: Option<String>,
+}
+
 #[derive(Debug, Serialize, PartialEq, Deserialize, Clone)]
 #[serde(deny_unknown_fields, rename_all = "kebab-case")]
 pub struct ChompTaskMaybeTemplated {
@@ -155,6 +206,7 @@ pub struct ChompTaskMaybeTemplated {
     pub validation: Option<ValidationCheck>,
     pub display: Option<TaskDisplay>,
     pub stdio: Option<TaskStdio>,
+    pub output: Option<TaskOutput>,
     pub engine: Option<ChompEngine>,
     pub run: Option<String>,
     pub cwd: Option<String>,
@@ -164,6 +216,16 @@ pub struct ChompTaskMaybeTemplated {
     pub template_options: Option<HashMap<String, toml::value::Value>>,
     pub env: Option<HashMap<String, String>>,
     pub env_default: Option<HashMap<String, String>>,
+    pub env_file: Option<Vec<String>>,
+    pub cache: Option<bool>,
+    pub timeout: Option<f64>,
+    pub retries: Option<u32>,
+    pub retry_delay: Option<f64>,
+    pub weight: Option<usize>,
+    pub resources: Option<Vec<String>>,
+    pub service: Option<bool>,
+    pub ready: Option<ServiceReady>,
+    pub condition: Option<TaskCondition>,
 }
 
 impl ChompTaskMaybeTemplated {
@@ -182,16 +244,60 @@ impl ChompTaskMaybeTemplated {
             env_replace: None,
             env: None,
             env_default: None,
+            env_file: None,
             echo: None,
             invalidation: None,
             validation: None,
             serial: None,
             stdio: None,
+            output: None,
             template: None,
             template_options: None,
             watch_invalidation: None,
+            cache: None,
+            timeout: None,
+            retries: None,
+            retry_delay: None,
+            weight: None,
+            resources: None,
+            service: None,
+            ready: None,
+            condition: None,
         }
     }
+    // digest of the task fields that affect its build, used to invalidate targets when
+    // they change. Unset fields are omitted so that adding new task options does not
+    // change the fingerprints of existing tasks.
+    pub fn fingerprint(&self) -> Result<String> {
+        let fields = [
+            ("run", serde_json::to_value(&self.run)?),
+            ("engine", serde_json::to_value(self.engine)?),
+            ("cwd", serde_json::to_value(&self.cwd)?),
+            ("env", serde_json::to_value(&self.env)?),
+            ("env-default", serde_json::to_value(&self.env_default)?),
+            ("env-file", serde_json::to_value(&self.env_file)?),
+            ("env-replace", serde_json::to_value(self.env_replace)?),
+            ("target", serde_json::to_value(&self.target)?),
+            ("targets", serde_json::to_value(&self.targets)?),
+            ("dep", serde_json::to_value(&self.dep)?),
+            ("deps", serde_json::to_value(&self.deps)?),
+            ("args", serde_json::to_value(&self.args)?),
+            ("template", serde_json::to_value(&self.template)?),
+            (
+                "template-options",
+                serde_json::to_value(&self.template_options)?,
+            ),
+        ];
+        // serializing through a JSON map sorts the keys of the map fields
+        let value: serde_json::Map<String, serde_json::Value> = fields
+            .into_iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(field, value)| (field.to_string(), value))
+            .collect();
+        Ok(hash(
+            serde_json::Value::Object(value).to_string().as_bytes(),
+        ))
+    }
     pub fn targets_vec(&self, cwd: &str) -> Result<Vec<String>> {
         if let Some(ref target) = self.target {
             let target_str = resolve_path(target, cwd);
@@ -258,6 +364,7 @@ pub struct ChompTaskMaybeTemplatedJs {
     pub watch_invalidation: Option<WatchInvalidation>,
     pub display: Option<TaskDisplay>,
     pub stdio: Option<TaskStdio>,
+    pub output: Option<TaskOutput>,
     pub engine: Option<ChompEngine>,
     pub run: Option<String>,
     pub cwd: Option<String>,
@@ -267,6 +374,16 @@ pub struct ChompTaskMaybeTemplatedJs {
     pub template_options: Option<HashMap<String, toml::value::Value>>,
     pub env: Option<HashMap<String, String>>,
     pub env_default: Option<HashMap<String, String>>,
+    pub env_file: Option<Vec<String>>,
+    pub cache: Option<bool>,
+    pub timeout: Option<f64>,
+    pub retries: Option<u32>,
+    pub retry_delay: Option<f64>,
+    pub weight: Option<usize>,
+    pub resources: Option<Vec<String>>,
+    pub service: Option<bool>,
+    pub ready: Option<ServiceReady>,
+    pub condition: Option<TaskCondition>,
 }
 
 impl From<ChompTaskMaybeTemplatedJs> for ChompTaskMaybeTemplated {
@@ -279,6 +396,7 @@ impl From<ChompTaskMaybeTemplatedJs> for ChompTaskMaybeTemplated {
             targets: val.targets,
             display: val.display,
             stdio: val.stdio,
+            output: val.output,
             invalidation: val.invalidation,
             validation: val.validation,
             dep: val.dep,
@@ -288,11 +406,21 @@ impl From<ChompTaskMaybeTemplatedJs> for ChompTaskMaybeTemplated {
             env_replace: val.env_replace,
             env: val.env,
             env_default: val.env_default,
+            env_file: val.env_file,
             run: val.run,
             engine: val.engine,
             template: val.template,
             template_options: val.template_options,
             watch_invalidation: val.watch_invalidation,
+            cache: val.cache,
+            timeout: val.timeout,
+            retries: val.retries,
+            retry_delay: val.retry_delay,
+            weight: val.weight,
+            resources: val.resources,
+            service: val.service,
+            ready: val.ready,
+            condition: val.condition,
         }
     }
 }
@@ -16,13 +16,176 @@
 
 use crate::chompfile::TaskStdio;
 use crate::engines::BatchCmd;
+use crate::ui;
+use futures::future::Future;
 use regex::Regex;
 use std::collections::BTreeMap;
 use std::env;
 use std::fs;
+#[cfg(not(target_os = "windows"))]
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::time::Duration;
+#[cfg(target_os = "linux")]
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::{Mutex, Once},
+};
 use tokio::process::{Child, Command};
+#[cfg(target_os = "linux")]
+use tokio::sync::Notify;
+
+// On Linux, tasks that do not read from the terminal are spawned in their own
+// process group so that terminating a task also terminates the processes it
+// spawned, eg for `sh -c 'npm run x'`. Tasks inheriting stdin from a terminal
+// stay in the foreground group so that they can still read from it.
+// Grouped task processes no longer receive terminal signals, so the live groups
+// are tracked to forward interrupts to them.
+#[cfg(target_os = "linux")]
+lazy_static! {
+    static ref PROCESS_GROUPS: Mutex<HashSet<u32>> = Mutex::new(HashSet::new());
+    static ref INTERRUPT: Notify = Notify::new();
+}
+
+#[cfg(target_os = "linux")]
+static INTERRUPT_HANDLER: Once = Once::new();
+
+#[cfg(target_os = "linux")]
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Time between SIGTERM and SIGKILL when terminating a process group, which also
+// bounds the wait for terminated tasks to exit
+const TERMINATE_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+#[cfg(target_os = "linux")]
+fn spawn(command: &mut Command, group: bool) -> std::io::Result<Child> {
+    if !group {
+        return command.spawn();
+    }
+    command.process_group(0);
+    let child = command.spawn()?;
+    if let Some(pid) = child.id() {
+        PROCESS_GROUPS.lock().unwrap().insert(pid);
+        install_interrupt_handler();
+    }
+    Ok(child)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn spawn(command: &mut Command, _group: bool) -> std::io::Result<Child> {
+    command.spawn()
+}
+
+// Until a task runs in its own group, interrupts reach all task processes
+// through the terminal and Chomp exits on them as usual. Afterwards every
+// interrupt is handled, a repeated one cutting the grace period short.
+#[cfg(target_os = "linux")]
+fn install_interrupt_handler() {
+    INTERRUPT_HANDLER.call_once(|| {
+        tokio::spawn(async {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                INTERRUPTED.store(true, Ordering::SeqCst);
+                INTERRUPT.notify_one();
+            }
+        });
+    });
+}
+
+#[cfg(target_os = "linux")]
+fn signal_process_group(pgid: u32, signal: libc::c_int) {
+    unsafe {
+        libc::kill(-(pgid as libc::pid_t), signal);
+    }
+}
+
+// The group id cannot be reused until its leader is reaped, which releases it
+#[cfg(target_os = "linux")]
+pub fn is_process_group(pgid: u32) -> bool {
+    PROCESS_GROUPS.lock().unwrap().contains(&pgid)
+}
+
+// SIGTERM to the whole group, followed by SIGKILL if it is still running after
+// the grace period
+#[cfg(target_os = "linux")]
+pub fn terminate_process_group(pgid: u32) {
+    signal_process_group(pgid, libc::SIGTERM);
+    tokio::spawn(async move {
+        tokio::time::sleep(TERMINATE_GRACE_PERIOD).await;
+        if is_process_group(pgid) {
+            signal_process_group(pgid, libc::SIGKILL);
+        }
+    });
+}
+
+#[cfg(target_os = "linux")]
+pub fn release_process_group(pgid: u32) {
+    PROCESS_GROUPS.lock().unwrap().remove(&pgid);
+}
+
+// resolves when Chomp is interrupted while running grouped tasks
+#[cfg(target_os = "linux")]
+pub async fn interrupt_signal() {
+    INTERRUPT.notified().await;
+}
+
+#[cfg(not(target_os = "linux"))]
+pub async fn interrupt_signal() {
+    futures::future::pending::<()>().await;
+}
+
+#[cfg(target_os = "linux")]
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn interrupted() -> bool {
+    false
+}
+
+// SIGINT to all groups, waiting on the running tasks for the grace period or
+// until the next interrupt before killing the groups still running
+#[cfg(target_os = "linux")]
+pub async fn interrupt_process_groups(running: impl Future) {
+    let pgids: Vec<u32> = PROCESS_GROUPS.lock().unwrap().iter().copied().collect();
+    for &pgid in &pgids {
+        signal_process_group(pgid, libc::SIGINT);
+    }
+    tokio::select! {
+        _ = tokio::time::timeout(TERMINATE_GRACE_PERIOD, running) => {}
+        _ = interrupt_signal() => {}
+    }
+    for pgid in pgids {
+        if is_process_group(pgid) {
+            signal_process_group(pgid, libc::SIGKILL);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub async fn interrupt_process_groups(running: impl Future) {
+    drop(running);
+}
+
+// waits on the terminated tasks for the grace period before killing the groups
+// still running, so that no task outlives Chomp
+#[cfg(target_os = "linux")]
+pub async fn kill_process_groups(running: impl Future) {
+    let pgids: Vec<u32> = PROCESS_GROUPS.lock().unwrap().iter().copied().collect();
+    let _ = tokio::time::timeout(TERMINATE_GRACE_PERIOD, running).await;
+    for pgid in pgids {
+        if is_process_group(pgid) {
+            signal_process_group(pgid, libc::SIGKILL);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub async fn kill_process_groups(running: impl Future) {
+    let _ = tokio::time::timeout(TERMINATE_GRACE_PERIOD, running).await;
+}
 
 fn replace_env_vars(arg: &str, env: &BTreeMap<String, String>) -> String {
     let mut out_arg = arg.to_string();
@@ -77,7 +240,7 @@ fn replace_env_vars(arg: &str, env: &BTreeMap<String, String>) -> String {
     out_arg
 }
 
-fn set_cmd_stdio(command: &mut Command, stdio: TaskStdio) {
+fn set_cmd_stdio(command: &mut Command, stdio: TaskStdio, capture: bool) {
     match stdio {
         TaskStdio::All => {}
         TaskStdio::StderrOnly => {
@@ -97,6 +260,15 @@ fn set_cmd_stdio(command: &mut Command, stdio: TaskStdio) {
             command.stderr(Stdio::null());
         }
     };
+    // captured output is forwarded by the pool
+    if capture {
+        if !matches!(stdio, TaskStdio::StderrOnly | TaskStdio::None) {
+            command.stdout(Stdio::piped());
+        }
+        if !matches!(stdio, TaskStdio::StdoutOnly | TaskStdio::None) {
+            command.stderr(Stdio::piped());
+        }
+    }
 }
 
 #[cfg(target_os = "windows")]
@@ -129,7 +301,7 @@ pub fn create_cmd(
         .unwrap();
     }
     if batch_cmd.echo {
-        println!("{}", &run);
+        ui::println(run);
     }
     // fast path for direct commands to skip the shell entirely
     if let Some(capture) = CMD.captures(run) {
@@ -183,7 +355,11 @@ pub fn create_cmd(
                     command.arg(arg_str);
                 }
             }
-            set_cmd_stdio(&mut command, batch_cmd.stdio.unwrap_or_default());
+            set_cmd_stdio(
+                &mut command,
+                batch_cmd.stdio.unwrap_or_default(),
+                batch_cmd.capture_output,
+            );
             match command.spawn() {
                 Ok(child) => return Some(child),
                 Err(_) => {
@@ -207,7 +383,11 @@ pub fn create_cmd(
                             command.arg(arg_str);
                         }
                     }
-                    set_cmd_stdio(&mut command, batch_cmd.stdio.unwrap_or_default());
+                    set_cmd_stdio(
+                        &mut command,
+                        batch_cmd.stdio.unwrap_or_default(),
+                        batch_cmd.capture_output,
+                    );
                     match command.spawn() {
                         Ok(child) => return Some(child),
                         Err(_) => {
@@ -255,7 +435,11 @@ pub fn create_cmd(
         command.env(name, value);
     }
     command.current_dir(cwd);
-    set_cmd_stdio(&mut command, batch_cmd.stdio.unwrap_or_default());
+    set_cmd_stdio(
+        &mut command,
+        batch_cmd.stdio.unwrap_or_default(),
+        batch_cmd.capture_output,
+    );
     Some(command.spawn().unwrap())
 }
 
@@ -267,6 +451,9 @@ pub fn create_cmd(
     fastpath_fallback: bool,
 ) -> Option<Child> {
     let run = batch_cmd.run.trim();
+    let stdio = batch_cmd.stdio.unwrap_or_default();
+    // tasks reading from the terminal must stay in its foreground process group
+    let group = !matches!(stdio, TaskStdio::All) || !std::io::stdin().is_terminal();
     lazy_static! {
         static ref CMD: Regex = Regex::new(
             "(?x)
@@ -290,7 +477,7 @@ pub fn create_cmd(
     }
 
     if batch_cmd.echo {
-        println!("{}", run);
+        ui::println(run);
     }
     // Spawn needs an exact path for Ubuntu?
     // fast path for direct commands to skip the shell entirely
@@ -334,8 +521,8 @@ pub fn create_cmd(
                     command.arg(arg_str);
                 }
             }
-            set_cmd_stdio(&mut command, batch_cmd.stdio.unwrap_or_default());
-            match command.spawn() {
+            set_cmd_stdio(&mut command, stdio, batch_cmd.capture_output);
+            match spawn(&mut command, group) {
                 Ok(child) => return Some(child),
                 Err(_) => {
                     if !fastpath_fallback {
@@ -355,6 +542,6 @@ pub fn create_cmd(
     command.arg("-e");
     command.arg("-c");
     command.arg(&run);
-    set_cmd_stdio(&mut command, batch_cmd.stdio.unwrap_or_default());
-    Some(command.spawn().unwrap())
+    set_cmd_stdio(&mut command, stdio, batch_cmd.capture_output);
+    Some(spawn(&mut command, group).unwrap())
 }
@@ -19,6 +19,7 @@ use crate::engines::create_cmd;
 use crate::engines::CmdPool;
 use crate::engines::Exec;
 use crate::engines::{BatchCmd, ExecState};
+use crate::ui;
 use futures::future::FutureExt;
 use std::env;
 use std::time::Instant;
@@ -45,7 +46,7 @@ pub fn deno_runner(cmd_pool: &mut CmdPool, mut cmd: BatchCmd, targets: Vec<Strin
     let write_future = fs::write(tmp_file, cmd.run.to_string());
     cmd.run = DENO_CMD.to_string();
     let exec_num = cmd_pool.exec_num;
-    cmd_pool.exec_cnt += 1;
+    let (weight, resources) = cmd_pool.acquire(&cmd);
     let pool = cmd_pool as *mut CmdPool;
     let echo = cmd.echo;
     cmd.echo = false;
@@ -57,33 +58,23 @@ pub fn deno_runner(cmd_pool: &mut CmdPool, mut cmd: BatchCmd, targets: Vec<Strin
     );
     let future = async move {
         let cmd_pool = unsafe { &mut *pool };
-        let exec = &mut cmd_pool.execs.get_mut(&exec_num).unwrap();
         write_future.await.expect("unable to write temporary file");
-        exec.child.as_ref()?;
+        if cmd_pool.execs[&exec_num].child.is_none() {
+            cmd_pool.release(exec_num);
+            return None;
+        }
         if echo {
-            println!("<Deno exec>");
+            ui::println("<Deno exec>");
         }
-        exec.state = match exec.child.as_mut().unwrap().wait().await {
-            Ok(status) => {
-                if status.success() {
-                    ExecState::Completed
-                } else {
-                    ExecState::Failed
-                }
-            }
-            Err(e) => match exec.state {
-                ExecState::Terminating => ExecState::Terminated,
-                _ => panic!("Unexpected exec error {:?}", e),
-            },
-        };
-        cmd_pool.exec_cnt -= 1;
+        let state = cmd_pool.wait_exec(exec_num).await;
+        cmd_pool.release(exec_num);
         fs::remove_file(&tmp_file2)
             .await
             .expect("unable to cleanup tmp file");
         let end_time = Instant::now();
         // finally we verify that the targets exist
         let mtime = check_target_mtimes(targets, true).await;
-        Some((exec.state, mtime, end_time - start_time))
+        Some((state, mtime, end_time - start_time))
     }
     .boxed_local()
     .shared();
@@ -95,7 +86,16 @@ pub fn deno_runner(cmd_pool: &mut CmdPool, mut cmd: BatchCmd, targets: Vec<Strin
             child,
             future,
             state: ExecState::Executing,
+            weight,
+            resources,
+            start_time,
+            forwarders: Vec::new(),
+            grouped: None,
+            logs: Vec::new(),
+            stderr: None,
+            exit_code: None,
         },
     );
+    cmd_pool.forward_exec_output(exec_num);
     cmd_pool.exec_num += 1;
 }
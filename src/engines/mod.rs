@@ -19,29 +19,47 @@ mod deno;
 mod node;
 
 use crate::chompfile::ChompEngine;
+use crate::chompfile::TaskOutput;
 use crate::chompfile::TaskStdio;
 use crate::engines::deno::deno_runner;
 use crate::engines::node::node_runner;
 use crate::extensions::BatcherResult;
+use crate::logs;
+use crate::reporter::{Event, Reporter};
 use crate::task::check_target_mtimes;
+use crate::trace::{SpanArgs, Trace};
+use crate::ui;
 use crate::ExtensionEnvironment;
 use anyhow::Result;
 use anyhow::{anyhow, Error};
 use cmd::create_cmd;
+pub use cmd::{interrupt_process_groups, interrupt_signal, interrupted, kill_process_groups};
+#[cfg(target_os = "linux")]
+use cmd::{is_process_group, release_process_group, terminate_process_group};
 use futures::future::Shared;
-use futures::future::{Future, FutureExt};
+use futures::future::{join_all, pending, Future, FutureExt};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::env;
+use std::io::Write;
 use std::path::Path;
 use std::pin::Pin;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use std::time::Instant;
 use tokio::fs;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::net::TcpStream;
 use tokio::process::Child;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time;
 use tokio::time::sleep;
 
 pub fn replace_env_vars_static(arg: &str, env: &BTreeMap<String, String>) -> String {
@@ -74,15 +92,92 @@ pub fn replace_env_vars_static(arg: &str, env: &BTreeMap<String, String>) -> Str
     out_arg
 }
 
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// how long to wait for the remaining captured output after a process exits, in case
+// a background process it spawned keeps the output open
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+enum OutputSink {
+    Direct,
+    Prefixed(Vec<u8>),
+    // stdout and stderr lines in order, written when the exec completes
+    Grouped(Arc<Mutex<Vec<(bool, Vec<u8>)>>>),
+}
+
+#[derive(Clone)]
+struct ExecOutput {
+    sink: OutputSink,
+    logs: Arc<Mutex<Vec<std::fs::File>>>,
+    // stderr retained for the JUnit report
+    stderr: Option<Arc<Mutex<Vec<u8>>>>,
+    ready: Option<(Regex, Arc<Notify>)>,
+    // keeps stdout free for the JSON reporter events
+    stdout_to_stderr: bool,
+}
+
+// forwards captured process output line by line, copying it into the task logs and
+// notifying on the first line matching the readiness pattern
+async fn forward_output<R: AsyncRead + Unpin>(reader: R, stderr: bool, output: ExecOutput) {
+    let ExecOutput {
+        sink,
+        logs,
+        stderr: stderr_buffer,
+        mut ready,
+        stdout_to_stderr,
+    } = output;
+    let to_stderr = stderr || stdout_to_stderr;
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        match &sink {
+            OutputSink::Direct => ui::write_output(to_stderr, &line),
+            OutputSink::Prefixed(prefix) => {
+                let mut prefixed = prefix.clone();
+                prefixed.extend_from_slice(&line);
+                ui::write_output(to_stderr, &prefixed);
+            }
+            OutputSink::Grouped(lines) => lines.lock().unwrap().push((to_stderr, line.clone())),
+        }
+        for log in logs.lock().unwrap().iter_mut() {
+            let _ = log.write_all(&line);
+        }
+        if let (true, Some(stderr_buffer)) = (stderr, &stderr_buffer) {
+            stderr_buffer.lock().unwrap().extend_from_slice(&line);
+        }
+        if let Some((pattern, notify)) = &ready {
+            if pattern.is_match(&String::from_utf8_lossy(&line)) {
+                notify.notify_one();
+                ready = None;
+            }
+        }
+    }
+}
+
 pub struct CmdPool<'a> {
     cmd_num: usize,
     pub extension_env: &'a mut ExtensionEnvironment,
+    pub reporter: Reporter,
+    pub trace: Option<Trace>,
+    // whether to retain the stderr of execs, for the JUnit report
+    pub capture_stderr: bool,
     cmds: BTreeMap<usize, CmdOp>,
     exec_num: usize,
     execs: BTreeMap<usize, Exec<'a>>,
-    exec_cnt: usize,
+    // total weight of the executing commands
+    exec_weight: usize,
+    // remaining tokens of each named resource
+    resources: HashMap<String, usize>,
     batching: BTreeSet<usize>,
     cmd_execs: BTreeMap<usize, usize>,
+    // readiness log patterns of service commands, by cmd num
+    ready_logs: HashMap<usize, (Regex, Arc<Notify>)>,
     cwd: String,
     path: String,
     pool_size: usize,
@@ -100,6 +195,14 @@ pub struct CmdOp {
     pub stdio: TaskStdio,
     pub targets: Vec<String>,
     pub echo: bool,
+    pub timeout: Option<Duration>,
+    pub capture_output: bool,
+    pub output: TaskOutput,
+    // job name of the task log the captured output is written to
+    pub log: Option<String>,
+    pub weight: usize,
+    pub resources: Vec<String>,
+    pub priority: usize,
 }
 
 #[derive(Debug, Deserialize, Serialize, Hash, PartialEq, Eq)]
@@ -113,6 +216,8 @@ pub struct BatchCmd {
     pub engine: ChompEngine,
     pub stdio: Option<TaskStdio>,
     pub ids: Vec<usize>,
+    #[serde(skip)]
+    pub capture_output: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -122,6 +227,7 @@ pub enum ExecState {
     Failed,
     Terminating,
     Terminated,
+    TimedOut,
 }
 
 #[derive(Debug)]
@@ -129,6 +235,17 @@ pub struct Exec<'a> {
     cmd: BatchCmd,
     child: Option<Child>,
     state: ExecState,
+    // weight and resources held until the exec completes
+    weight: usize,
+    resources: Vec<String>,
+    start_time: Instant,
+    // forwarding of the captured output
+    forwarders: Vec<JoinHandle<()>>,
+    grouped: Option<Arc<Mutex<Vec<(bool, Vec<u8>)>>>>,
+    logs: Vec<String>,
+    stderr: Option<Arc<Mutex<Vec<u8>>>>,
+    // exit code of the process, when it exited normally
+    exit_code: Option<i32>,
     future:
         Shared<Pin<Box<dyn Future<Output = Option<(ExecState, Option<Duration>, Duration)>> + 'a>>>,
 }
@@ -136,6 +253,7 @@ pub struct Exec<'a> {
 impl<'a> CmdPool<'a> {
     pub fn new(
         pool_size: usize,
+        resources: HashMap<String, usize>,
         cwd: String,
         extension_env: &'a mut ExtensionEnvironment,
     ) -> CmdPool<'a> {
@@ -164,29 +282,111 @@ impl<'a> CmdPool<'a> {
             path,
             cmds: BTreeMap::new(),
             exec_num: 0,
-            exec_cnt: 0,
+            exec_weight: 0,
+            resources,
             execs: BTreeMap::new(),
             pool_size,
             extension_env,
+            reporter: Reporter::Text,
+            trace: None,
+            capture_stderr: false,
             batching: BTreeSet::new(),
             cmd_execs: BTreeMap::new(),
+            ready_logs: HashMap::new(),
             batch_future: None,
         }
     }
 
     pub fn terminate(&mut self, cmd_num: usize, name: &str) {
-        // Note: On Windows, terminating a process does not terminate
+        // Note: On Windows and macOS, terminating a process does not terminate
         // the child processes, which can leave zombie processes behind
-        println!("Terminating {}...", name);
-        let exec_num = self.cmd_execs.get(&cmd_num).unwrap();
-        let exec = &mut self.execs.get_mut(exec_num).unwrap();
+        if self.reporter.is_json() {
+            self.reporter.report(Event::Terminate { job: name });
+        } else {
+            ui::println(&format!("Terminating {}...", name));
+        }
+        let exec_num = *self.cmd_execs.get(&cmd_num).unwrap();
+        self.kill_exec(exec_num);
+    }
+
+    fn kill_exec(&mut self, exec_num: usize) {
+        let exec = &mut self.execs.get_mut(&exec_num).unwrap();
         if matches!(exec.state, ExecState::Executing) {
             exec.state = ExecState::Terminating;
             let child = exec.child.as_mut().unwrap();
+            #[cfg(target_os = "linux")]
+            if let Some(pid) = child.id().filter(|&pid| is_process_group(pid)) {
+                terminate_process_group(pid);
+                return;
+            }
             child.start_kill().expect("Unable to terminate process");
         }
     }
 
+    // waits on the exec process, killing it once the shortest timeout of its cmds elapses
+    async fn wait_exec(&mut self, exec_num: usize) -> ExecState {
+        let timeout = self.execs[&exec_num]
+            .cmd
+            .ids
+            .iter()
+            .filter_map(|id| self.cmds[id].timeout)
+            .min();
+        let child = self
+            .execs
+            .get_mut(&exec_num)
+            .unwrap()
+            .child
+            .as_mut()
+            .unwrap();
+        #[cfg(target_os = "linux")]
+        let pid = child.id();
+        let mut timed_out = false;
+        let status = match timeout {
+            Some(timeout) => match time::timeout(timeout, child.wait()).await {
+                Ok(status) => status,
+                Err(_) => {
+                    timed_out = true;
+                    self.kill_exec(exec_num);
+                    let exec = self.execs.get_mut(&exec_num).unwrap();
+                    exec.child.as_mut().unwrap().wait().await
+                }
+            },
+            None => child.wait().await,
+        };
+        #[cfg(target_os = "linux")]
+        if let Some(pid) = pid {
+            release_process_group(pid);
+        }
+        let exec = self.execs.get_mut(&exec_num).unwrap();
+        let forwarders = std::mem::take(&mut exec.forwarders);
+        let _ = time::timeout(OUTPUT_DRAIN_TIMEOUT, join_all(forwarders)).await;
+        let exec = self.execs.get_mut(&exec_num).unwrap();
+        if let Some(grouped) = exec.grouped.take() {
+            ui::write_group(&grouped.lock().unwrap());
+        }
+        exec.exit_code = status.as_ref().ok().and_then(|status| status.code());
+        exec.state = match status {
+            Ok(_) if timed_out => ExecState::TimedOut,
+            Ok(status) => {
+                if status.success() {
+                    ExecState::Completed
+                } else {
+                    ExecState::Failed
+                }
+            }
+            Err(e) => match exec.state {
+                ExecState::Terminating => ExecState::Terminated,
+                _ => panic!("Unexpected exec error {:?}", e),
+            },
+        };
+        if matches!(exec.state, ExecState::Failed | ExecState::TimedOut) {
+            for name in &exec.logs {
+                logs::keep_failed_log(&self.cwd, name);
+            }
+        }
+        exec.state
+    }
+
     pub fn get_exec_future(
         &mut self,
         cmd_num: usize,
@@ -217,6 +417,71 @@ impl<'a> CmdPool<'a> {
         }.boxed_local()
     }
 
+    pub fn exec_stderr(&self, cmd_num: usize) -> Option<Vec<u8>> {
+        let exec = &self.execs[self.cmd_execs.get(&cmd_num)?];
+        Some(exec.stderr.as_ref()?.lock().unwrap().clone())
+    }
+
+    pub fn exec_exit_code(&self, cmd_num: usize) -> Option<i32> {
+        self.execs[self.cmd_execs.get(&cmd_num)?].exit_code
+    }
+
+    pub fn is_executing(&self, cmd_num: usize) -> bool {
+        match self.cmd_execs.get(&cmd_num) {
+            Some(exec_num) => matches!(self.execs[exec_num].state, ExecState::Executing),
+            None => false,
+        }
+    }
+
+    // resolves once the exec for the cmd has started and met all of the given readiness
+    // conditions, never resolving if the exec could not be started
+    pub fn get_ready_future(
+        &mut self,
+        cmd_num: usize,
+        log: Option<Regex>,
+        port: Option<u16>,
+        file: Option<String>,
+    ) -> Pin<Box<dyn Future<Output = ()> + 'a>> {
+        // registered before the exec is created, so that no output is missed
+        let log_ready = log.map(|log| {
+            let log_ready = Arc::new(Notify::new());
+            self.ready_logs.insert(cmd_num, (log, log_ready.clone()));
+            log_ready
+        });
+        let pool = self as *mut CmdPool;
+        async move {
+            let this = unsafe { &mut *pool };
+            let exec_num = loop {
+                if let Some(&exec_num) = this.cmd_execs.get(&cmd_num) {
+                    break exec_num;
+                }
+                if this.batch_future.is_none() {
+                    this.create_batch_future();
+                }
+                if this.batch_future.as_ref().unwrap().clone().await.is_err() {
+                    return pending().await;
+                }
+            };
+            if this.execs[&exec_num].child.is_none() {
+                return pending().await;
+            }
+            if let Some(log_ready) = log_ready {
+                log_ready.notified().await;
+            }
+            if let Some(port) = port {
+                while TcpStream::connect(("localhost", port)).await.is_err() {
+                    sleep(READY_POLL_INTERVAL).await;
+                }
+            }
+            if let Some(file) = file {
+                while fs::metadata(&file).await.is_err() {
+                    sleep(READY_POLL_INTERVAL).await;
+                }
+            }
+        }
+        .boxed_local()
+    }
+
     fn create_batch_future(&mut self) {
         // This is bad Rust, but it's also totally fine given the static execution model
         // (in Zig it might even be called idomatic)...
@@ -283,13 +548,27 @@ impl<'a> CmdPool<'a> {
                     this.execs.get_mut(&exec_num).unwrap().cmd.ids.push(cmd_num);
                 }
                 for cmd in batched.drain(..) {
+                    // batched execs are admitted like any other command, and
+                    // otherwise batched again once the pool frees up
+                    let (weight, resources) = this.requirements(&cmd.ids);
+                    if !this.has_capacity(weight) || !this.has_resources(&resources) {
+                        this.batching.extend(cmd.ids.iter().copied());
+                        continue;
+                    }
                     this.new_exec(cmd).await;
                 }
-                // any leftover unbatched just get batched
+                // any leftover unbatched just get batched, as long as the pool
+                // has capacity for their weight and resources, highest priority first
+                let mut batch: Vec<&CmdOp> = batch.into_iter().collect();
+                batch.sort_by_key(|cmd| (Reverse(cmd.priority), cmd.id));
                 for cmd in batch {
-                    if this.exec_cnt == this.pool_size {
+                    // lower priority commands must not take the pool from a heavier one
+                    if !this.has_capacity(cmd.weight) {
                         break;
                     }
+                    if !this.has_resources(&cmd.resources) {
+                        continue;
+                    }
                     this.batching.remove(&cmd.id);
                     this.new_exec(BatchCmd {
                         id: None,
@@ -300,6 +579,7 @@ impl<'a> CmdPool<'a> {
                         env: cmd.env.clone(),
                         stdio: Some(cmd.stdio),
                         ids: vec![cmd.id],
+                        capture_output: cmd.capture_output,
                     })
                     .await;
                 }
@@ -312,15 +592,168 @@ impl<'a> CmdPool<'a> {
         );
     }
 
+    fn has_capacity(&self, weight: usize) -> bool {
+        // a command heavier than the whole pool still runs on its own
+        self.exec_weight == 0 || self.exec_weight + weight <= self.pool_size
+    }
+
+    fn has_resources(&self, resources: &[String]) -> bool {
+        resources
+            .iter()
+            .all(|resource| self.resources.get(resource).is_some_and(|&n| n > 0))
+    }
+
+    // A batched exec takes the largest weight of its commands and one token of
+    // each resource any of them uses
+    fn requirements(&self, ids: &[usize]) -> (usize, Vec<String>) {
+        let mut weight = 0;
+        let mut resources: Vec<String> = Vec::new();
+        for id in ids {
+            let op = &self.cmds[id];
+            weight = weight.max(op.weight);
+            for resource in &op.resources {
+                if !resources.contains(resource) {
+                    resources.push(resource.to_string());
+                }
+            }
+        }
+        (weight, resources)
+    }
+
+    // the exec records the resource tokens it actually took, so that release
+    // returns exactly those
+    fn acquire(&mut self, cmd: &BatchCmd) -> (usize, Vec<String>) {
+        let (weight, resources) = self.requirements(&cmd.ids);
+        self.exec_weight += weight;
+        let resources = resources
+            .into_iter()
+            .filter(|resource| match self.resources.get_mut(resource) {
+                Some(n) if *n > 0 => {
+                    *n -= 1;
+                    true
+                }
+                _ => false,
+            })
+            .collect();
+        (weight, resources)
+    }
+
+    // captured output is forwarded line by line through the ui, in the output mode of
+    // the exec commands
+    fn forward_exec_output(&mut self, exec_num: usize) {
+        let exec = self.execs.get_mut(&exec_num).unwrap();
+        let ready = exec
+            .cmd
+            .ids
+            .iter()
+            .find_map(|id| self.ready_logs.remove(id));
+        let cmds: Vec<&CmdOp> = exec.cmd.ids.iter().map(|id| &self.cmds[id]).collect();
+        let sink = if cmds
+            .iter()
+            .any(|cmd| matches!(cmd.output, TaskOutput::Grouped))
+        {
+            let lines = Arc::new(Mutex::new(Vec::new()));
+            exec.grouped = Some(lines.clone());
+            OutputSink::Grouped(lines)
+        } else if cmds
+            .iter()
+            .any(|cmd| matches!(cmd.output, TaskOutput::Prefixed))
+        {
+            let names: Vec<&str> = cmds
+                .iter()
+                .map(|cmd| cmd.name.as_deref().unwrap_or(&cmd.run))
+                .collect();
+            OutputSink::Prefixed(format!("\x1b[34m[{}]\x1b[0m ", names.join(", ")).into_bytes())
+        } else {
+            OutputSink::Direct
+        };
+        let child = match exec.child.as_mut() {
+            Some(child) => child,
+            None => return,
+        };
+        exec.logs = cmds.iter().filter_map(|cmd| cmd.log.clone()).collect();
+        let logs = Arc::new(Mutex::new(
+            exec.logs
+                .iter()
+                .filter_map(|name| logs::create_log(&self.cwd, name))
+                .collect::<Vec<_>>(),
+        ));
+        if self.capture_stderr {
+            exec.stderr = Some(Arc::new(Mutex::new(Vec::new())));
+        }
+        let output = ExecOutput {
+            sink,
+            logs,
+            stderr: exec.stderr.clone(),
+            ready,
+            stdout_to_stderr: self.reporter.is_json(),
+        };
+        if let Some(stdout) = child.stdout.take() {
+            exec.forwarders
+                .push(tokio::spawn(forward_output(stdout, false, output.clone())));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            exec.forwarders
+                .push(tokio::spawn(forward_output(stderr, true, output)));
+        }
+    }
+
+    // called once the exec has completed
+    fn release(&mut self, exec_num: usize) {
+        let exec = &self.execs[&exec_num];
+        self.exec_weight -= exec.weight;
+        for resource in &exec.resources {
+            if let Some(n) = self.resources.get_mut(resource) {
+                *n += 1;
+            }
+        }
+        if let Some(trace) = &mut self.trace {
+            let jobs: Vec<String> = exec
+                .cmd
+                .ids
+                .iter()
+                .map(|id| {
+                    let cmd = &self.cmds[id];
+                    cmd.name.clone().unwrap_or_else(|| cmd.run.clone())
+                })
+                .collect();
+            let name = if jobs.len() == 1 {
+                jobs[0].clone()
+            } else {
+                format!("batch of {}", jobs.len())
+            };
+            trace.add_span(
+                name,
+                exec.start_time,
+                SpanArgs {
+                    exec: exec_num,
+                    engine: format!("{:?}", exec.cmd.engine).to_lowercase(),
+                    state: format!("{:?}", exec.state).to_lowercase(),
+                    jobs,
+                },
+            );
+        }
+    }
+
     async fn new_exec(&mut self, mut cmd: BatchCmd) {
         let exec_num = self.exec_num;
         cmd.id = Some(exec_num);
+        // batcher execs capture output if any of their commands do
+        cmd.capture_output = cmd.ids.iter().any(|id| self.cmds[id].capture_output);
 
         let mut targets = Vec::new();
         for id in &cmd.ids {
             let cmd = &self.cmds[id];
             if let Some(name) = &cmd.name {
-                println!("\x1b[1m▶ {}\x1b[0m", name);
+                if self.reporter.is_json() {
+                    self.reporter.report(Event::Start { job: name });
+                } else {
+                    if ui::is_active() {
+                        ui::job_started(name);
+                    } else {
+                        println!("\x1b[1m▶ {}\x1b[0m", name);
+                    }
+                }
             }
             for target in &cmd.targets {
                 let target_path = Path::new(target);
@@ -343,7 +776,7 @@ impl<'a> CmdPool<'a> {
         match cmd.engine {
             ChompEngine::Shell => {
                 let start_time = Instant::now();
-                self.exec_cnt += 1;
+                let (weight, resources) = self.acquire(&cmd);
                 let child = create_cmd(
                     cmd.cwd.as_ref().unwrap_or(&self.cwd),
                     &self.path,
@@ -352,25 +785,12 @@ impl<'a> CmdPool<'a> {
                 );
                 let future = async move {
                     let this = unsafe { &mut *pool };
-                    let exec = &mut this.execs.get_mut(&exec_num).unwrap();
-                    exec.state = match exec.child.as_mut().unwrap().wait().await {
-                        Ok(status) => {
-                            if status.success() {
-                                ExecState::Completed
-                            } else {
-                                ExecState::Failed
-                            }
-                        }
-                        Err(e) => match exec.state {
-                            ExecState::Terminating => ExecState::Terminated,
-                            _ => panic!("Unexpected exec error {:?}", e),
-                        },
-                    };
+                    let state = this.wait_exec(exec_num).await;
                     let end_time = Instant::now();
-                    this.exec_cnt -= 1;
+                    this.release(exec_num);
                     // finally we verify that the targets exist
                     let mtime = check_target_mtimes(targets, true).await;
-                    Some((exec.state, mtime, end_time - start_time))
+                    Some((state, mtime, end_time - start_time))
                 }
                 .boxed_local()
                 .shared();
@@ -381,8 +801,17 @@ impl<'a> CmdPool<'a> {
                         child,
                         future,
                         state: ExecState::Executing,
+                        weight,
+                        resources,
+                        start_time,
+                        forwarders: Vec::new(),
+                        grouped: None,
+                        logs: Vec::new(),
+                        stderr: None,
+                        exit_code: None,
                     },
                 );
+                self.forward_exec_output(exec_num);
                 self.exec_num += 1;
             }
             ChompEngine::Node => node_runner(self, cmd, targets),
@@ -401,6 +830,13 @@ impl<'a> CmdPool<'a> {
         engine: ChompEngine,
         stdio: TaskStdio,
         echo: bool,
+        timeout: Option<Duration>,
+        capture_output: bool,
+        output: TaskOutput,
+        log: Option<String>,
+        weight: usize,
+        resources: Vec<String>,
+        priority: usize,
     ) -> usize {
         let id = self.cmd_num;
         let run = if matches!(engine, ChompEngine::Shell) && replacements {
@@ -420,6 +856,13 @@ impl<'a> CmdPool<'a> {
                 engine,
                 stdio,
                 targets,
+                timeout,
+                capture_output,
+                output,
+                log,
+                weight,
+                resources,
+                priority,
             },
         );
         self.cmd_num = id + 1;
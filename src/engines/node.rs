@@ -19,6 +19,7 @@ use crate::engines::create_cmd;
 use crate::engines::CmdPool;
 use crate::engines::Exec;
 use crate::engines::{BatchCmd, ExecState};
+use crate::ui;
 use base64::{engine::general_purpose, Engine as _};
 use futures::future::FutureExt;
 use percent_encoding::percent_encode;
@@ -47,7 +48,7 @@ pub fn node_runner(cmd_pool: &mut CmdPool, mut cmd: BatchCmd, targets: Vec<Strin
     cmd.echo = false;
     let run_clone = if echo { Some(cmd.run.clone()) } else { None };
     let exec_num = cmd_pool.exec_num;
-    cmd_pool.exec_cnt += 1;
+    let (weight, resources) = cmd_pool.acquire(&cmd);
     let pool = cmd_pool as *mut CmdPool;
     let child = create_cmd(
         cmd.cwd.as_ref().unwrap_or(&cmd_pool.cwd),
@@ -57,29 +58,19 @@ pub fn node_runner(cmd_pool: &mut CmdPool, mut cmd: BatchCmd, targets: Vec<Strin
     );
     let future = async move {
         let cmd_pool = unsafe { &mut *pool };
-        let exec = &mut cmd_pool.execs.get_mut(&exec_num).unwrap();
-        exec.child.as_ref()?;
+        if cmd_pool.execs[&exec_num].child.is_none() {
+            cmd_pool.release(exec_num);
+            return None;
+        }
         if echo {
-            println!("{}", run_clone.as_ref().unwrap());
+            ui::println(run_clone.as_ref().unwrap());
         }
-        exec.state = match exec.child.as_mut().unwrap().wait().await {
-            Ok(status) => {
-                if status.success() {
-                    ExecState::Completed
-                } else {
-                    ExecState::Failed
-                }
-            }
-            Err(e) => match exec.state {
-                ExecState::Terminating => ExecState::Terminated,
-                _ => panic!("Unexpected exec error {:?}", e),
-            },
-        };
-        cmd_pool.exec_cnt -= 1;
+        let state = cmd_pool.wait_exec(exec_num).await;
+        cmd_pool.release(exec_num);
         let end_time = Instant::now();
         // finally we verify that the targets exist
         let mtime = check_target_mtimes(targets, true).await;
-        Some((exec.state, mtime, end_time - start_time))
+        Some((state, mtime, end_time - start_time))
     }
     .boxed_local()
     .shared();
@@ -91,7 +82,16 @@ pub fn node_runner(cmd_pool: &mut CmdPool, mut cmd: BatchCmd, targets: Vec<Strin
             child,
             future,
             state: ExecState::Executing,
+            weight,
+            resources,
+            start_time,
+            forwarders: Vec::new(),
+            grouped: None,
+            logs: Vec::new(),
+            stderr: None,
+            exit_code: None,
         },
     );
+    cmd_pool.forward_exec_output(exec_num);
     cmd_pool.exec_num += 1;
 }
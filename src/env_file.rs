@@ -0,0 +1,131 @@
+// Chomp Task Runner
+// Copyright (C) 2022  Guy Bedford
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use anyhow::{anyhow, Result};
+use std::fs;
+
+// the variables of a dotenv file, in the order they are defined
+pub fn load_env_file(path: &str) -> Result<Vec<(String, String)>> {
+    let source =
+        fs::read_to_string(path).map_err(|e| anyhow!("Unable to read env file {}: {}", path, e))?;
+    parse_env_file(&source).map_err(|e| anyhow!("Invalid env file {} - {}", path, e))
+}
+
+// KEY=VALUE lines with an optional "export" prefix and # comments, where
+// single-quoted values are literal and double-quoted values support escapes,
+// both of which may span multiple lines
+fn parse_env_file(source: &str) -> Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    let mut lines = source.lines().enumerate();
+    while let Some((idx, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line
+            .strip_prefix("export ")
+            .map(str::trim_start)
+            .unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("line {}, expected KEY=VALUE", idx + 1))?;
+        let key = key.trim_end();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        {
+            return Err(anyhow!("line {}, invalid variable name '{}'", idx + 1, key));
+        }
+        let value = value.trim_start();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let mut raw = value[1..].to_string();
+                let end = loop {
+                    if let Some(end) = closing_quote(&raw, quote) {
+                        break end;
+                    }
+                    match lines.next() {
+                        Some((_, next)) => {
+                            raw.push('\n');
+                            raw.push_str(next);
+                        }
+                        None => {
+                            return Err(anyhow!(
+                                "line {}, unterminated quoted value for {}",
+                                idx + 1,
+                                key
+                            ))
+                        }
+                    }
+                };
+                let rest = raw[end + 1..].trim();
+                if !rest.is_empty() && !rest.starts_with('#') {
+                    return Err(anyhow!(
+                        "line {}, unexpected characters after the quoted value for {}",
+                        idx + 1,
+                        key
+                    ));
+                }
+                raw.truncate(end);
+                if quote == '"' {
+                    unescape(&raw)
+                } else {
+                    raw
+                }
+            }
+            _ => match value.find(" #") {
+                Some(comment) => value[..comment].trim_end().to_string(),
+                None => value.trim_end().to_string(),
+            },
+        };
+        vars.push((key.to_string(), value));
+    }
+    Ok(vars)
+}
+
+fn closing_quote(raw: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (idx, c) in raw.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' && quote == '"' {
+            escaped = true;
+        } else if c == quote {
+            return Some(idx);
+        }
+    }
+    None
+}
+
+fn unescape(raw: &str) -> String {
+    let mut value = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('r') => value.push('\r'),
+            Some('t') => value.push('\t'),
+            Some(c) => value.push(c),
+            None => value.push('\\'),
+        }
+    }
+    value
+}
@@ -17,6 +17,7 @@
 use crate::chompfile::ChompTaskMaybeTemplatedJs;
 use crate::engines::BatchCmd;
 use crate::engines::CmdOp;
+use crate::ui;
 use crate::ChompTaskMaybeTemplated;
 use crate::Chompfile;
 use anyhow::{anyhow, Error, Result};
@@ -148,15 +149,26 @@ pub fn expand_template_tasks(
             echo: task.echo,
             display: task.display,
             stdio: Some(task.stdio.unwrap_or_default()),
+            output: task.output,
             serial: task.serial,
             env_replace: task.env_replace,
             env: task.env,
             env_default: task.env_default,
+            env_file: task.env_file,
             run: task.run,
             engine: task.engine,
             template: None,
             template_options: task.template_options,
             watch_invalidation: task.watch_invalidation,
+            cache: task.cache,
+            timeout: task.timeout,
+            retries: task.retries,
+            retry_delay: task.retry_delay,
+            weight: task.weight,
+            resources: task.resources,
+            service: task.service,
+            ready: task.ready,
+            condition: task.condition,
         };
         let mut template_tasks: Vec<ChompTaskMaybeTemplatedJs> =
             extension_env.run_template(template, &js_task)?;
@@ -202,7 +214,7 @@ fn chomp_log(
         msg.push_str(&arg.to_rust_string_lossy(scope));
         i += 1;
     }
-    println!("{}", &msg);
+    ui::println(&msg);
 }
 
 fn chomp_include(
@@ -14,11 +14,12 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use crate::ui;
 use anyhow::{anyhow, Result};
 use dirs::home_dir;
-use http_body_util::{BodyExt, Empty};
+use http_body_util::{BodyExt, Empty, Full};
 use hyper::body::Bytes;
-use hyper::Uri;
+use hyper::{Method, Request, StatusCode, Uri};
 use hyper_tls::HttpsConnector;
 use hyper_util::client::legacy::{connect::HttpConnector, Client};
 use hyper_util::rt::TokioExecutor;
@@ -26,25 +27,32 @@ use sha2::{Digest, Sha256};
 use std::path::PathBuf;
 use tokio::fs;
 
-fn chomp_cache_dir() -> PathBuf {
-    let mut path = home_dir().unwrap();
+// CHOMP_CACHE_DIR overrides the default ~/.chomp/cache folder
+pub fn chomp_cache_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("CHOMP_CACHE_DIR").filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+    let mut path = home_dir()
+        .ok_or_else(|| anyhow!("Unable to determine the home directory for the Chomp cache"))?;
     path.push(".chomp");
     path.push("cache");
-    path
+    Ok(path)
 }
 
-pub async fn clear_cache() -> std::io::Result<()> {
-    match fs::remove_dir_all(chomp_cache_dir()).await {
+pub async fn clear_cache() -> Result<()> {
+    match fs::remove_dir_all(chomp_cache_dir()?).await {
         Ok(()) => Ok(()),
         Err(e) => match e.kind() {
             std::io::ErrorKind::NotFound => Ok(()),
-            _ => Err(e),
+            _ => Err(e.into()),
         },
     }
 }
 
 pub async fn prep_cache() -> Result<()> {
-    let _ = fs::create_dir_all(chomp_cache_dir()).await;
+    if let Ok(dir) = chomp_cache_dir() {
+        let _ = fs::create_dir_all(dir).await;
+    }
     Ok(())
 }
 
@@ -66,7 +74,7 @@ pub fn hash(input: &[u8]) -> String {
 }
 
 async fn from_cache(cache_key: &str) -> Option<String> {
-    let mut path = chomp_cache_dir();
+    let mut path = chomp_cache_dir().ok()?;
     path.push(cache_key);
     match fs::read_to_string(&path).await {
         Ok(cached) => Some(cached),
@@ -78,7 +86,7 @@ async fn from_cache(cache_key: &str) -> Option<String> {
 }
 
 async fn write_cache(cache_key: &str, source: &str) -> Result<()> {
-    let mut path = chomp_cache_dir();
+    let mut path = chomp_cache_dir()?;
     path.push(cache_key);
     fs::write(&path, source).await?;
     Ok(())
@@ -90,7 +98,7 @@ pub async fn fetch_uri_cached(uri_str: &str, uri: Uri) -> Result<String> {
         return Ok(cached);
     }
 
-    println!("\x1b[34;1mFetch\x1b[0m {}", &uri_str);
+    ui::println(&format!("\x1b[34;1mFetch\x1b[0m {}", &uri_str));
     let https = HttpsConnector::new();
     let client: Client<HttpsConnector<HttpConnector>, Empty<Bytes>> =
         Client::builder(TokioExecutor::new()).build(https);
@@ -105,3 +113,37 @@ pub async fn fetch_uri_cached(uri_str: &str, uri: Uri) -> Result<String> {
     write_cache(&hash, &result).await?;
     Ok(result)
 }
+
+// None = NotFound
+pub async fn get(uri: Uri) -> Result<Option<Bytes>> {
+    let https = HttpsConnector::new();
+    let client: Client<HttpsConnector<HttpConnector>, Empty<Bytes>> =
+        Client::builder(TokioExecutor::new()).build(https);
+
+    let uri_str = uri.to_string();
+    let res = client.get(uri).await?;
+    if res.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if res.status() != 200 {
+        return Err(anyhow!("{} for URL {}", res.status(), uri_str));
+    }
+    Ok(Some(res.into_body().collect().await?.to_bytes()))
+}
+
+pub async fn put(uri: Uri, body: Vec<u8>) -> Result<()> {
+    let https = HttpsConnector::new();
+    let client: Client<HttpsConnector<HttpConnector>, Full<Bytes>> =
+        Client::builder(TokioExecutor::new()).build(https);
+
+    let uri_str = uri.to_string();
+    let req = Request::builder()
+        .method(Method::PUT)
+        .uri(uri)
+        .body(Full::new(Bytes::from(body)))?;
+    let res = client.request(req).await?;
+    if !res.status().is_success() {
+        return Err(anyhow!("{} for URL {}", res.status(), uri_str));
+    }
+    Ok(())
+}
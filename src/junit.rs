@@ -0,0 +1,108 @@
+// Chomp Task Runner
+// Copyright (C) 2022  Guy Bedford
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fmt::Write;
+use std::fs;
+use std::time::Duration;
+
+pub enum TestResult {
+    Passed,
+    Failed(String),
+    Skipped(&'static str),
+}
+
+pub struct TestCase {
+    pub name: String,
+    pub time: Duration,
+    pub result: TestResult,
+}
+
+// XML text and attribute escaping, dropping the ANSI color codes and other control
+// characters that are not valid in XML
+fn escape(text: &str) -> String {
+    lazy_static! {
+        static ref ANSI: Regex = Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").unwrap();
+    }
+    let mut escaped = String::with_capacity(text.len());
+    for c in ANSI.replace_all(text, "").chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn write_junit(path: &str, cases: &[TestCase]) -> Result<()> {
+    let failures = cases
+        .iter()
+        .filter(|case| matches!(case.result, TestResult::Failed(_)))
+        .count();
+    let skipped = cases
+        .iter()
+        .filter(|case| matches!(case.result, TestResult::Skipped(_)))
+        .count();
+    let time: Duration = cases.iter().map(|case| case.time).sum();
+    let counts = format!(
+        "tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\"",
+        cases.len(),
+        failures,
+        skipped,
+        time.as_secs_f64()
+    );
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(xml, "<testsuites name=\"chomp\" {}>", counts)?;
+    writeln!(xml, "  <testsuite name=\"chomp\" {}>", counts)?;
+    for case in cases {
+        write!(
+            xml,
+            "    <testcase name=\"{}\" classname=\"chomp\" time=\"{:.3}\"",
+            escape(&case.name),
+            case.time.as_secs_f64()
+        )?;
+        match &case.result {
+            TestResult::Passed => xml.push_str("/>\n"),
+            TestResult::Failed(stderr) => {
+                writeln!(
+                    xml,
+                    ">\n      <failure message=\"{} failed\">{}</failure>\n    </testcase>",
+                    escape(&case.name),
+                    escape(stderr)
+                )?;
+            }
+            TestResult::Skipped(reason) => {
+                writeln!(
+                    xml,
+                    ">\n      <skipped message=\"{}\"/>\n    </testcase>",
+                    reason
+                )?;
+            }
+        }
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    fs::write(path, xml)?;
+    Ok(())
+}
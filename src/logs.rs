@@ -0,0 +1,90 @@
+// Chomp Task Runner
+// Copyright (C) 2022  Guy Bedford
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+// Task output logs are retained in `.chomp/logs` next to the Chompfile, keyed by the job
+// display name. The log of the latest failed run is also kept as `<name>.failed.log`.
+fn logs_dir(cwd: &str) -> PathBuf {
+    let mut path = PathBuf::from(cwd);
+    path.push(".chomp");
+    path.push("logs");
+    path
+}
+
+// job names include path separators and interpolation characters
+fn file_stem(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+pub fn log_path(cwd: &str, name: &str) -> PathBuf {
+    let mut path = logs_dir(cwd);
+    path.push(format!("{}.log", file_stem(name)));
+    path
+}
+
+pub fn failed_log_path(cwd: &str, name: &str) -> PathBuf {
+    let mut path = logs_dir(cwd);
+    path.push(format!("{}.failed.log", file_stem(name)));
+    path
+}
+
+pub fn create_log(cwd: &str, name: &str) -> Option<fs::File> {
+    fs::create_dir_all(logs_dir(cwd)).ok()?;
+    fs::File::create(log_path(cwd, name)).ok()
+}
+
+pub fn keep_failed_log(cwd: &str, name: &str) {
+    let _ = fs::copy(log_path(cwd, name), failed_log_path(cwd, name));
+}
+
+pub fn print_log(cwd: &str, name: &str) -> Result<()> {
+    let name = name.strip_prefix(':').unwrap_or(name);
+    let path = log_path(cwd, name);
+    let log = match fs::read(&path) {
+        Ok(log) => log,
+        Err(_) => {
+            return Err(anyhow!(
+                "No log found for \x1b[1m{}\x1b[0m. Either the task has not run yet, or its output\nwas written directly to the terminal, which is not logged.",
+                name
+            ));
+        }
+    };
+    std::io::stdout().write_all(&log)?;
+    // point to the failure when the task has since succeeded
+    let failed_path = failed_log_path(cwd, name);
+    if let (Ok(log_meta), Ok(failed_meta)) = (fs::metadata(&path), fs::metadata(&failed_path)) {
+        if failed_meta.modified()? < log_meta.modified()? {
+            eprintln!(
+                "\n\x1b[1mThe log of the latest failure of {} is at {}\x1b[0m",
+                name,
+                failed_path.to_str().unwrap()
+            );
+        }
+    }
+    Ok(())
+}
@@ -38,12 +38,20 @@ use std::fs::canonicalize;
 use tokio::sync::mpsc::unbounded_channel;
 
 mod ansi_windows;
+mod cache;
 mod chompfile;
 mod engines;
+mod env_file;
 mod extensions;
 mod http_client;
+mod junit;
+mod logs;
+mod reporter;
 mod server;
+mod state;
 mod task;
+mod trace;
+mod ui;
 
 use std::path::PathBuf;
 
@@ -153,7 +161,7 @@ async fn main() -> Result<()> {
             Arg::new("clear_cache")
                 .short('C')
                 .long("clear-cache")
-                .help("Clear URL extension cache")
+                .help("Clear URL extension and artifact caches")
                 .action(ArgAction::SetTrue),
         )
         .arg(
@@ -170,6 +178,81 @@ async fn main() -> Result<()> {
                 .help("Force rebuild targets")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("dry_run")
+                .short('n')
+                .long("dry-run")
+                .help("Print the tasks that would run without running them")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("keep_going")
+                .short('k')
+                .long("keep-going")
+                .help("Keep running the tasks that do not depend on a failed task")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("fail_fast")
+                .long("fail-fast")
+                .help("Terminate all running tasks on the first task failure")
+                .conflicts_with("keep_going")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no_progress")
+                .long("no-progress")
+                .help("Disable the live progress display of the running tasks")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("junit")
+                .long("junit")
+                .value_name("FILE")
+                .help("Write a JUnit XML report of the task results to the given file"),
+        )
+        .arg(
+            Arg::new("logs")
+                .long("logs")
+                .value_name("TASK")
+                .help("Print the output log of the last run of a task"),
+        )
+        .arg(
+            Arg::new("timings")
+                .long("timings")
+                .help("Report the slowest tasks and the time spent per task after the run")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("trace")
+                .long("trace")
+                .value_name("FILE")
+                .help("Write a Chrome trace of the task executions to the given file"),
+        )
+        .arg(
+            Arg::new("explain")
+                .long("explain")
+                .value_name("TARGET")
+                .help("Explain why the target would rerun without running it"),
+        )
+        .arg(
+            Arg::new("graph")
+                .long("graph")
+                .value_name("FORMAT")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("dot")
+                .value_parser(["dot", "json"])
+                .help("Print the resolved task graph of the targets as dot or json"),
+        )
+        .arg(
+            Arg::new("reporter")
+                .long("reporter")
+                .value_name("REPORTER")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .help("Output format of the run progress"),
+        )
         .arg(
             Arg::new("target")
                 .value_name("TARGET")
@@ -269,14 +352,25 @@ async fn main() -> Result<()> {
     };
     assert!(env::set_current_dir(&cwd).is_ok());
 
+    if let Some(task) = matches.get_one::<String>("logs") {
+        if !targets.is_empty() {
+            return Err(anyhow!("--logs does not take any other targets."));
+        }
+        return logs::print_log(&cwd.to_str().unwrap().replace('\\', "/"), task);
+    }
+
     if matches.get_flag("clear_cache") {
         http_client::clear_cache().await?;
-        println!("\x1b[1;32m√\x1b[0m Cleared remote URL extension cache.");
+        println!("\x1b[1;32m√\x1b[0m Cleared remote URL extension and artifact caches.");
         if targets.is_empty() {
             return Ok(());
         }
     }
 
+    if matches.get_one::<String>("reporter").unwrap() == "json" {
+        ui::status_to_stderr();
+    }
+
     init_js_platform();
 
     let pool_size = match matches.get_one::<usize>("jobs") {
@@ -544,6 +638,50 @@ async fn main() -> Result<()> {
         targets
     };
 
+    if (matches.get_flag("dry_run")
+        || matches.contains_id("explain")
+        || matches.contains_id("graph"))
+        && (matches.get_flag("serve") || matches.get_flag("watch"))
+    {
+        return Err(anyhow!(
+            "Cannot use --dry-run, --explain or --graph with --watch or --serve."
+        ));
+    }
+    if matches.get_flag("fail_fast") && (matches.get_flag("serve") || matches.get_flag("watch")) {
+        return Err(anyhow!("Cannot use --fail-fast with --watch or --serve."));
+    }
+    if matches.get_flag("timings") && (matches.get_flag("serve") || matches.get_flag("watch")) {
+        return Err(anyhow!("Cannot use --timings with --watch or --serve."));
+    }
+    if matches.contains_id("trace") && (matches.get_flag("serve") || matches.get_flag("watch")) {
+        return Err(anyhow!("Cannot use --trace with --watch or --serve."));
+    }
+    if matches.contains_id("junit") && (matches.get_flag("serve") || matches.get_flag("watch")) {
+        return Err(anyhow!("Cannot use --junit with --watch or --serve."));
+    }
+    let reporter = match matches.get_one::<String>("reporter").unwrap().as_str() {
+        "json" => reporter::Reporter::Json,
+        _ => reporter::Reporter::Text,
+    };
+    if reporter.is_json()
+        && (matches.contains_id("explain")
+            || matches.contains_id("graph")
+            || matches.get_flag("timings"))
+    {
+        return Err(anyhow!(
+            "--reporter json cannot be used with --explain, --graph or --timings."
+        ));
+    }
+    let targets = match matches.get_one::<String>("explain") {
+        Some(explain) => {
+            if matches.contains_id("target") {
+                return Err(anyhow!("--explain does not take any other targets."));
+            }
+            vec![explain.to_string()]
+        }
+        None => targets,
+    };
+
     let mut runner = Runner::new(
         &chompfile,
         &mut extension_env,
@@ -556,6 +694,28 @@ async fn main() -> Result<()> {
                 watch: matches.get_flag("serve") || matches.get_flag("watch"),
                 force: matches.get_flag("force"),
                 rerun: matches.get_flag("rerun"),
+                dry_run: matches.get_flag("dry_run"),
+                explain: matches.contains_id("explain"),
+                graph: match matches.get_one::<String>("graph").map(|s| s.as_str()) {
+                    Some("json") => Some(task::GraphFormat::Json),
+                    Some(_) => Some(task::GraphFormat::Dot),
+                    None => None,
+                },
+                reporter,
+                keep_going: matches.get_flag("keep_going"),
+                fail_fast: matches.get_flag("fail_fast"),
+                timings: matches.get_flag("timings"),
+                trace: matches.get_one::<String>("trace").cloned(),
+                junit: matches.get_one::<String>("junit").cloned(),
+                // the live progress display replaces the task start lines on a terminal
+                progress: !matches.get_flag("no_progress")
+                    && ui::supported()
+                    && !reporter.is_json()
+                    && !matches.get_flag("serve")
+                    && !matches.get_flag("watch")
+                    && !matches.get_flag("dry_run")
+                    && !matches.contains_id("explain")
+                    && !matches.contains_id("graph"),
                 args: if !args.is_empty() { Some(args) } else { None },
                 pool_size,
                 targets,
@@ -566,6 +726,10 @@ async fn main() -> Result<()> {
         )
         .await?;
 
+    if engines::interrupted() {
+        std::process::exit(130);
+    }
+
     if !ok {
         eprintln!("Unable to complete all tasks.");
     }
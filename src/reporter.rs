@@ -0,0 +1,92 @@
+// Chomp Task Runner
+// Copyright (C) 2022  Guy Bedford
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use serde::Serialize;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Reporter {
+    #[default]
+    Text,
+    // newline-delimited JSON events on stdout
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    Start {
+        job: &'a str,
+    },
+    Invalidated {
+        job: &'a str,
+        reason: String,
+    },
+    Cached {
+        job: &'a str,
+    },
+    ConditionUnmet {
+        job: &'a str,
+        reason: String,
+    },
+    Finish {
+        job: &'a str,
+        status: &'static str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        duration_ms: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        exit_code: Option<i32>,
+    },
+    Retry {
+        job: &'a str,
+        attempt: u32,
+        attempts: u32,
+    },
+    Terminate {
+        job: &'a str,
+    },
+    ServiceExit {
+        job: &'a str,
+        success: bool,
+    },
+    Watch {
+        path: &'a str,
+    },
+    Summary {
+        succeeded: Vec<String>,
+        failed: Vec<String>,
+        skipped: Vec<String>,
+        cancelled: Vec<String>,
+        unmet: Vec<String>,
+        cached: usize,
+    },
+}
+
+impl Reporter {
+    pub fn is_json(self) -> bool {
+        matches!(self, Reporter::Json)
+    }
+
+    pub fn report(self, event: Event) {
+        if self.is_json() {
+            println!("{}", serde_json::to_string(&event).unwrap());
+        }
+    }
+}
+
+pub fn duration_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,119 @@
+// Chomp Task Runner
+// Copyright (C) 2022  Guy Bedford
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::http_client::hash;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind::NotFound;
+use std::path::PathBuf;
+
+// Build state persisted between runs in `.chomp/state.json` next to the Chompfile.
+// Records are keyed by the job display name, which is stable across runs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BuildState {
+    #[serde(default)]
+    jobs: BTreeMap<String, JobRecord>,
+    // last successful command duration of each job, in milliseconds
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    durations: BTreeMap<String, f64>,
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct JobRecord {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    // the resolved run and env can hold secrets from env files, so the state only
+    // stores their digest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmd_digest: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deps_digest: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dep_hashes: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub target_hashes: BTreeMap<String, String>,
+}
+
+impl BuildState {
+    pub fn load(cwd: &str) -> BuildState {
+        let mut path = PathBuf::from(cwd);
+        path.push(".chomp");
+        path.push("state.json");
+        // an unreadable or outdated state file is treated as empty, since it only
+        // ever provides additional invalidation information over mtimes
+        let mut state: BuildState = match fs::read_to_string(&path) {
+            Ok(source) => serde_json::from_str(&source).unwrap_or_default(),
+            Err(_) => BuildState::default(),
+        };
+        state.path = path;
+        state
+    }
+
+    pub fn get(&self, key: &str) -> Option<&JobRecord> {
+        self.jobs.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> &mut JobRecord {
+        self.dirty = true;
+        self.jobs.entry(key.to_string()).or_default()
+    }
+
+    pub fn duration(&self, key: &str) -> Option<f64> {
+        self.durations.get(key).copied()
+    }
+
+    pub fn set_duration(&mut self, key: &str, duration_ms: f64) {
+        self.dirty = true;
+        self.durations.insert(key.to_string(), duration_ms);
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        fs::create_dir_all(self.path.parent().unwrap())?;
+        fs::write(&self.path, serde_json::to_string_pretty(&self)?)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+pub fn cmd_digest(run: &str, env: &BTreeMap<String, String>) -> String {
+    hash(json!({ "run": run, "env": env }).to_string().as_bytes())
+}
+
+pub fn deps_digest(deps: &[String]) -> String {
+    hash(json!(deps).to_string().as_bytes())
+}
+
+// None = NotFound, directories and other unreadable paths hash as empty
+pub fn hash_file(path: &str) -> Option<String> {
+    match fs::read(path) {
+        Ok(source) => Some(hash(&source)),
+        Err(e) => match e.kind() {
+            NotFound => None,
+            _ => Some(String::new()),
+        },
+    }
+}
@@ -14,33 +14,48 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use crate::cache::{
+    artifact_key, restore_artifacts, restore_remote_artifacts, store_artifacts, upload_artifacts,
+};
 use crate::chompfile::{
-    resolve_path, ChompTaskMaybeTemplated, Chompfile, InvalidationCheck, TaskDisplay,
-    ValidationCheck, WatchInvalidation,
+    resolve_path, ChompEngine, ChompTaskMaybeTemplated, Chompfile, InvalidationCheck,
+    TaskCondition, TaskDisplay, TaskOutput, TaskStdio, ValidationCheck, WatchInvalidation,
 };
 use crate::engines::CmdPool;
+use crate::env_file::load_env_file;
+use crate::junit::{write_junit, TestCase, TestResult};
+use crate::reporter::{duration_ms, Event, Reporter};
 use crate::server::FileEvent;
+use crate::state::{cmd_digest, deps_digest, hash_file, BuildState, JobRecord};
+use crate::trace::Trace;
+use crate::ui;
 use crate::ExtensionEnvironment;
 use async_recursion::async_recursion;
 use capturing_glob::{glob, Pattern};
 use futures::future::Shared;
-use futures::future::{select_all, Future, FutureExt};
+use futures::future::{join_all, select, select_all, Future, FutureExt};
 use notify_debouncer_mini::{new_debouncer, DebounceEventResult};
 use pathdiff::diff_paths;
+use regex::Regex;
+use std::cell::Cell;
+use std::cmp::Reverse;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::env::current_dir;
+use std::fmt;
 use std::fs::canonicalize;
 use std::io::ErrorKind::NotFound;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 use std::path::Path;
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::rc::Rc;
 use std::sync::mpsc::{Receiver, TryRecvError};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
 extern crate notify;
 
 // Path-only event from the file watcher. The notify-debouncer-mini debouncer collapses
@@ -50,6 +65,9 @@ pub type WatchEvent = PathBuf;
 
 use crate::engines::replace_env_vars_static;
 use crate::engines::ExecState;
+use crate::engines::{
+    interrupt_process_groups, interrupt_signal, interrupted, kill_process_groups,
+};
 use anyhow::{anyhow, Result};
 use derivative::Derivative;
 use futures::executor;
@@ -58,12 +76,18 @@ use std::sync::mpsc::channel;
 use tokio::fs;
 use tokio::time;
 
+// number of jobs listed in the slowest jobs of the --timings report
+const TIMINGS_SLOWEST: usize = 10;
+
 #[derive(Debug)]
 pub struct Task<'a> {
     name: Option<String>,
     targets: Vec<String>,
     deps: Vec<String>,
+    // resolved paths of the existing env files
+    env_files: Vec<String>,
     env: BTreeMap<String, String>,
+    fingerprint: String,
     chomp_task: &'a ChompTaskMaybeTemplated,
 }
 
@@ -76,6 +100,21 @@ pub struct RunOptions {
     pub watch: bool,
     pub rerun: bool,
     pub force: bool,
+    pub dry_run: bool,
+    pub explain: bool,
+    pub graph: Option<GraphFormat>,
+    pub reporter: Reporter,
+    pub keep_going: bool,
+    pub fail_fast: bool,
+    pub timings: bool,
+    pub trace: Option<String>,
+    pub progress: bool,
+    pub junit: Option<String>,
+}
+
+pub enum GraphFormat {
+    Dot,
+    Json,
 }
 
 #[derive(Hash, Eq, PartialEq, Debug, Clone, Copy)]
@@ -104,6 +143,15 @@ struct Job {
     mtime_future: Option<Shared<Pin<Box<dyn Future<Output = Option<Duration>>>>>>,
     targets: Vec<String>,
     cmd_num: Option<usize>,
+    record: Option<Box<JobRecord>>,
+    cache_key: Option<String>,
+    restored: bool,
+    // reason the task condition was not met, when skipped by it
+    unmet: Option<String>,
+    // retry attempts made for the current run
+    attempt: u32,
+    // cmd of the running service process, once ready
+    service_cmd: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -227,6 +275,31 @@ pub struct Runner<'a> {
     task_jobs: HashMap<String, usize>,
     file_nodes: HashMap<String, usize>,
     interpolate_nodes: Vec<usize>,
+
+    dry_run: bool,
+    keep_going: bool,
+    fail_fast: bool,
+    cancelled: bool,
+    outcomes: BTreeMap<usize, JobOutcome>,
+    // number of jobs made live by the run, for the progress count
+    live_jobs: usize,
+    // critical path lengths of the live jobs, reset whenever a job is made live
+    critical_paths: HashMap<usize, usize>,
+    // command durations of the jobs executed in this run
+    timings: Vec<(usize, Duration)>,
+    // jobs waiting out their retry-delay before the next attempt
+    retrying: HashSet<usize>,
+    // jobs fetching their targets from the remote cache, set on a hit
+    fetching: HashMap<usize, Rc<Cell<bool>>>,
+    // stderr of the failed jobs, for the JUnit report
+    failure_output: HashMap<usize, String>,
+    reporter: Reporter,
+    explain: bool,
+    // job target mtime and invalidation at the time of the run check
+    explanations: HashMap<usize, (Option<Duration>, Option<Invalidation>)>,
+    state: BuildState,
+    remote_cache_write: bool,
+    uploads: Vec<JoinHandle<()>>,
 }
 
 impl<'a> Job {
@@ -242,6 +315,12 @@ impl<'a> Job {
             mtime: None,
             cmd_num: None,
             mtime_future: None,
+            record: None,
+            cache_key: None,
+            restored: false,
+            unmet: None,
+            attempt: 0,
+            service_cmd: None,
         }
     }
 
@@ -283,6 +362,44 @@ impl<'a> Job {
     }
 }
 
+// The reason a job is rerun rather than treated as cached
+#[derive(Debug, Clone)]
+enum Invalidation {
+    NoTargets,
+    TargetMissing,
+    Args,
+    Force,
+    Always,
+    By(String),
+    TargetModified(String),
+}
+
+impl fmt::Display for Invalidation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Invalidation::NoTargets => write!(f, "no targets"),
+            Invalidation::TargetMissing => write!(f, "target missing"),
+            Invalidation::Args => write!(f, "args"),
+            Invalidation::Force => write!(f, "forced"),
+            Invalidation::Always => write!(f, "always"),
+            Invalidation::By(dep) => write!(f, "invalidated by {}", dep),
+            Invalidation::TargetModified(target) => write!(f, "target {} modified", target),
+        }
+    }
+}
+
+// The result of the last completion of a job, for the final run summary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JobOutcome {
+    Succeeded,
+    Cached,
+    Failed,
+    Skipped,
+    Cancelled,
+    // not run as the task condition was not met
+    Unmet,
+}
+
 #[derive(Hash, Eq, PartialEq, Debug, Clone)]
 enum JobOrFileState {
     Job(JobState),
@@ -416,9 +533,11 @@ fn now() -> std::time::Duration {
 fn create_task_env(
     task: &ChompTaskMaybeTemplated,
     chompfile: &Chompfile,
+    cwd: &str,
     replacements: bool,
-) -> BTreeMap<String, String> {
+) -> Result<BTreeMap<String, String>> {
     let mut env = BTreeMap::new();
+    apply_env_files(&mut env, &chompfile.env_file, cwd, replacements)?;
     for (item, value) in &chompfile.env {
         env.insert(
             item.to_uppercase(),
@@ -445,6 +564,9 @@ fn create_task_env(
             }
         }
     }
+    if let Some(ref task_env_file) = task.env_file {
+        apply_env_files(&mut env, task_env_file, cwd, replacements)?;
+    }
     if let Some(ref task_env) = task.env {
         for (item, value) in task_env {
             env.insert(
@@ -475,16 +597,18 @@ fn create_task_env(
             }
         }
     }
-    env
+    Ok(env)
 }
 
 #[cfg(not(target_os = "windows"))]
 fn create_task_env<'a>(
     task: &ChompTaskMaybeTemplated,
     chompfile: &'a Chompfile,
+    cwd: &str,
     replacements: bool,
-) -> BTreeMap<String, String> {
+) -> Result<BTreeMap<String, String>> {
     let mut env = BTreeMap::new();
+    apply_env_files(&mut env, &chompfile.env_file, cwd, replacements)?;
     for (item, value) in &chompfile.env {
         env.insert(
             item.to_uppercase(),
@@ -507,6 +631,9 @@ fn create_task_env<'a>(
             );
         }
     }
+    if let Some(ref task_env_file) = task.env_file {
+        apply_env_files(&mut env, task_env_file, cwd, replacements)?;
+    }
     if let Some(ref task_env) = task.env {
         for (item, value) in task_env {
             env.insert(
@@ -533,7 +660,88 @@ fn create_task_env<'a>(
             }
         }
     }
-    env
+    Ok(env)
+}
+
+// dotenv files define their variables unless already set in the system environment,
+// with later files taking precedence, and are skipped when they do not exist
+fn apply_env_files(
+    env: &mut BTreeMap<String, String>,
+    env_files: &[String],
+    cwd: &str,
+    replacements: bool,
+) -> Result<()> {
+    for env_file in env_files {
+        let path = resolve_path(env_file, cwd);
+        if !Path::new(&path).exists() {
+            continue;
+        }
+        for (item, value) in load_env_file(&path)? {
+            match std::env::var_os(&item) {
+                // system-defined vars are redefined for the powershell exec on Windows
+                Some(val) if cfg!(target_os = "windows") => {
+                    env.insert(item.to_uppercase(), val.to_string_lossy().to_string());
+                }
+                Some(_) => {}
+                None => {
+                    let value = if replacements {
+                        replace_env_vars_static(&value, env)
+                    } else {
+                        value
+                    };
+                    env.insert(item.to_uppercase(), value);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// the env files of a task, of which the existing ones are implicit deps of the task
+fn task_env_files(task: &ChompTaskMaybeTemplated, chompfile: &Chompfile, cwd: &str) -> Vec<String> {
+    chompfile
+        .env_file
+        .iter()
+        .chain(task.env_file.iter().flatten())
+        .map(|env_file| resolve_path(env_file, cwd))
+        .collect()
+}
+
+// the first condition of the task that is not met, if any
+fn condition_unmet(
+    condition: &TaskCondition,
+    env: &BTreeMap<String, String>,
+    interpolate: Option<&String>,
+    cwd: &str,
+) -> Option<String> {
+    if let Some(name) = &condition.env {
+        // variable names are matched uppercased, as for the task env
+        let upper_name = name.to_uppercase();
+        let set = match env.get(&upper_name) {
+            Some(value) => !value.is_empty(),
+            None => std::env::vars_os().any(|(key, value)| {
+                key.to_string_lossy().to_uppercase() == upper_name && !value.is_empty()
+            }),
+        };
+        if !set {
+            return Some(format!("{} not set", name));
+        }
+    }
+    if let Some(file) = &condition.file {
+        let file = match interpolate {
+            Some(interpolate) if file.contains('#') => replace_interpolate(file, interpolate),
+            _ => file.to_string(),
+        };
+        if !Path::new(&resolve_path(&file, cwd)).exists() {
+            return Some(format!("{} not found", file));
+        }
+    }
+    if let Some(os) = &condition.os {
+        if os != std::env::consts::OS {
+            return Some(format!("not {}", os));
+        }
+    }
+    None
 }
 
 impl<'a> Runner<'a> {
@@ -547,7 +755,12 @@ impl<'a> Runner<'a> {
         let cwd_buf = current_dir()?;
         let cwd = cwd_buf.to_str().unwrap().replace('\\', "/");
 
-        let cmd_pool: CmdPool = CmdPool::new(pool_size, String::from(&cwd), extension_env);
+        let cmd_pool: CmdPool = CmdPool::new(
+            pool_size,
+            chompfile.resources.clone(),
+            String::from(&cwd),
+            extension_env,
+        );
         let mut runner = Runner {
             watch,
             // ui,
@@ -559,16 +772,37 @@ impl<'a> Runner<'a> {
             task_jobs: HashMap::new(),
             file_nodes: HashMap::new(),
             interpolate_nodes: Vec::new(),
+            dry_run: false,
+            keep_going: false,
+            fail_fast: false,
+            cancelled: false,
+            outcomes: BTreeMap::new(),
+            live_jobs: 0,
+            critical_paths: HashMap::new(),
+            timings: Vec::new(),
+            retrying: HashSet::new(),
+            fetching: HashMap::new(),
+            failure_output: HashMap::new(),
+            reporter: Reporter::Text,
+            explain: false,
+            explanations: HashMap::new(),
+            state: BuildState::load(&cwd),
+            // CI can populate the remote cache without changing the Chompfile
+            remote_cache_write: chompfile.cache.remote_write
+                || std::env::var("CHOMP_CACHE_WRITE").is_ok_and(|v| !v.is_empty() && v != "0"),
+            uploads: Vec::new(),
         };
 
         for task in &runner.chompfile.task {
             let targets = task.targets_vec(&cwd)?;
             let deps = task.deps_vec(chompfile, &cwd)?;
-            let env = create_task_env(task, chompfile, task.env_replace.unwrap_or(true));
+            let env = create_task_env(task, chompfile, &cwd, task.env_replace.unwrap_or(true))?;
             let task = Task {
                 name: task.name.clone(),
                 targets,
                 deps,
+                env_files: task_env_files(task, chompfile, &cwd),
+                fingerprint: task.fingerprint()?,
                 chomp_task: task,
                 env,
             };
@@ -738,9 +972,92 @@ impl<'a> Runner<'a> {
                 JobState::Fresh
             };
         }
+        if !failed {
+            self.record_job_state(job_num, cmd_time.is_some() || mtime.is_some());
+            if cmd_time.is_some() {
+                self.store_artifacts(job_num);
+            }
+        }
+        // jobs failed without running a command were skipped for a failed dep
+        let outcome = if failed && cmd_time.is_some() {
+            JobOutcome::Failed
+        } else if failed {
+            JobOutcome::Skipped
+        } else if self.get_job(job_num).unwrap().unmet.is_some() {
+            JobOutcome::Unmet
+        } else if cmd_time.is_some() || mtime.is_some() {
+            JobOutcome::Succeeded
+        } else {
+            JobOutcome::Cached
+        };
+        self.outcomes.insert(job_num, outcome);
+        if let (true, Some(cmd_num)) = (failed, self.get_job(job_num).unwrap().cmd_num) {
+            if let Some(stderr) = self.cmd_pool.exec_stderr(cmd_num) {
+                self.failure_output
+                    .insert(job_num, String::from_utf8_lossy(&stderr).to_string());
+            }
+        }
+        if let Some(cmd_time) = cmd_time {
+            self.timings.push((job_num, cmd_time));
+            if !failed {
+                let name = self
+                    .get_job(job_num)
+                    .unwrap()
+                    .display_name(&self.tasks, &self.cwd);
+                self.state.set_duration(&name, duration_ms(cmd_time));
+            }
+        }
         let job = self.get_job(job_num).unwrap();
         let task = &self.tasks[job.task];
-        if failed
+        if self.explain {
+            // explain output is only the final tree
+        } else if self.reporter.is_json() {
+            let name = job.display_name(&self.tasks, &self.cwd);
+            let duration_ms = cmd_time.map(duration_ms);
+            let exit_code = job
+                .cmd_num
+                .and_then(|cmd_num| self.cmd_pool.exec_exit_code(cmd_num));
+            self.reporter.report(if failed {
+                Event::Finish {
+                    job: &name,
+                    status: if cmd_time.is_some() {
+                        "failure"
+                    } else {
+                        "skipped"
+                    },
+                    duration_ms,
+                    exit_code,
+                }
+            } else if let Some(reason) = &job.unmet {
+                Event::ConditionUnmet {
+                    job: &name,
+                    reason: reason.to_string(),
+                }
+            } else if job.restored {
+                Event::Finish {
+                    job: &name,
+                    status: "restored",
+                    duration_ms,
+                    exit_code: None,
+                }
+            } else if job.service_cmd.is_some() {
+                Event::Finish {
+                    job: &name,
+                    status: "ready",
+                    duration_ms,
+                    exit_code: None,
+                }
+            } else if cmd_time.is_some() || mtime.is_some() {
+                Event::Finish {
+                    job: &name,
+                    status: "success",
+                    duration_ms,
+                    exit_code,
+                }
+            } else {
+                Event::Cached { job: &name }
+            });
+        } else if failed
             || matches!(
                 task.chomp_task.display,
                 Some(TaskDisplay::InitStatus)
@@ -758,9 +1075,14 @@ impl<'a> Runner<'a> {
                 name_bold.push_str("\x1b[0m");
                 name = name_bold;
             }
-            if matches!(task.chomp_task.display, Some(TaskDisplay::Dot)) {
+            let progress = ui::is_active();
+            if progress && !failed && cmd_time.is_none() && mtime.is_none() && job.unmet.is_none() {
+                // cached jobs are collapsed into the progress count
+            } else if matches!(task.chomp_task.display, Some(TaskDisplay::Dot)) && !progress {
                 if failed {
                     print!("\x1b[1;31m.\x1b[0m");
+                } else if job.unmet.is_some() {
+                    print!("\x1b[1m○\x1b[0m");
                 } else if mtime.is_some() || cmd_time.is_some() {
                     print!("\x1b[1;32m.\x1b[0m");
                 } else {
@@ -769,31 +1091,143 @@ impl<'a> Runner<'a> {
                 std::io::stdout().flush().unwrap();
             } else if let Some(cmd_time) = cmd_time {
                 if failed {
-                    println!(
+                    ui::println(&format!(
                         "\x1b[1;31mx\x1b[0m {} \x1b[34m[{:?}]\x1b[0m",
                         name, cmd_time
-                    );
+                    ));
                 } else {
-                    println!(
+                    ui::println(&format!(
                         "\x1b[1;32m√\x1b[0m {} \x1b[34m[{:?}]\x1b[0m",
                         name, cmd_time
-                    );
+                    ));
                 }
             } else {
                 if failed {
-                    println!("\x1b[1;31mx\x1b[0m {}", name);
+                    ui::println(&format!("\x1b[1;31mx\x1b[0m {}", name));
+                } else if let Some(reason) = &job.unmet {
+                    ui::println(&format!(
+                        "\x1b[1m○\x1b[0m {} \x1b[34m[condition not met: {}]\x1b[0m",
+                        name, reason
+                    ));
+                } else if job.restored {
+                    ui::println(&format!(
+                        "\x1b[1;32m√\x1b[0m {} \x1b[34m[restored]\x1b[0m",
+                        name
+                    ));
+                } else if job.service_cmd.is_some() {
+                    ui::println(&format!(
+                        "\x1b[1;32m√\x1b[0m {} \x1b[34m[ready]\x1b[0m",
+                        name
+                    ));
                 } else if mtime.is_some() {
-                    println!("\x1b[1;32m√\x1b[0m {}", name);
+                    ui::println(&format!("\x1b[1;32m√\x1b[0m {}", name));
                 } else if task.deps.is_empty() {
-                    println!("\x1b[1m●\x1b[0m {} \x1b[34m[exists]\x1b[0m", name);
+                    ui::println(&format!("\x1b[1m●\x1b[0m {} \x1b[34m[exists]\x1b[0m", name));
                 } else {
-                    println!("\x1b[1m●\x1b[0m {} \x1b[34m[cached]\x1b[0m", name);
+                    ui::println(&format!("\x1b[1m●\x1b[0m {} \x1b[34m[cached]\x1b[0m", name));
                 }
             }
         }
+        if ui::is_active() {
+            let cached = self
+                .outcomes
+                .values()
+                .filter(|outcome| matches!(outcome, JobOutcome::Cached))
+                .count();
+            ui::job_finished(
+                &job.display_name(&self.tasks, &self.cwd),
+                self.outcomes.len(),
+                cached,
+                self.live_jobs,
+            );
+        }
         {
             let job = self.get_job_mut(job_num).unwrap();
             job.cmd_num = None;
+            job.restored = false;
+            job.unmet = None;
+            job.attempt = 0;
+        }
+    }
+
+    fn store_artifacts(&mut self, job_num: usize) {
+        let key = self.get_job_mut(job_num).unwrap().cache_key.take();
+        if let Some(key) = key {
+            let job = self.get_job(job_num).unwrap();
+            if let Err(e) = store_artifacts(&key, &job.targets) {
+                ui::eprintln(&format!(
+                    "Unable to store {} in the artifact cache: {}",
+                    job.display_name(&self.tasks, &self.cwd),
+                    e
+                ));
+            }
+            if let Some(remote) = &self.chompfile.cache.remote {
+                if self.remote_cache_write {
+                    match upload_artifacts(remote, &key, &job.targets) {
+                        Ok(Some(upload)) => self.uploads.push(upload),
+                        Ok(None) => {}
+                        Err(e) => ui::eprintln(&format!(
+                            "Unable to upload {} to the remote cache: {}",
+                            job.display_name(&self.tasks, &self.cwd),
+                            e
+                        )),
+                    }
+                }
+            }
+        }
+    }
+
+    // records the command, env and digests of a successful job for the next run
+    fn record_job_state(&mut self, job_num: usize, executed: bool) {
+        let job = self.get_job_mut(job_num).unwrap();
+        let mut record = match job.record.take() {
+            Some(record) => *record,
+            None => return,
+        };
+        let job = self.get_job(job_num).unwrap();
+        let task = &self.tasks[job.task];
+        let key = job.display_name(&self.tasks, &self.cwd);
+        let existing = self.state.get(&key);
+        // target digests detect targets modified outside of Chomp, which only
+        // hash invalidation checks, to avoid hashing every target of every run
+        if matches!(task.chomp_task.invalidation, Some(InvalidationCheck::Hash)) {
+            record.target_hashes = match existing {
+                // cached targets are unchanged from when they were recorded
+                Some(existing) if !executed => existing.target_hashes.clone(),
+                _ => job
+                    .targets
+                    .iter()
+                    .map(|target| {
+                        (
+                            relative_path(target, &self.cwd),
+                            hash_file(target).unwrap_or_default(),
+                        )
+                    })
+                    .collect(),
+            };
+            record.dep_hashes = self.dep_hashes(job_num);
+        }
+        if existing != Some(&record) {
+            *self.state.get_mut(&key) = record;
+        }
+    }
+
+    // invalidation by a change of the resolved run, env or deps since the last successful run
+    fn cmd_changed(&self, job_num: usize, record: &JobRecord) -> Option<Invalidation> {
+        let job = self.get_job(job_num).unwrap();
+        match self.state.get(&job.display_name(&self.tasks, &self.cwd)) {
+            Some(existing) if existing.cmd_digest.is_some() => {
+                if existing.cmd_digest != record.cmd_digest
+                    || existing.fingerprint.is_some() && existing.fingerprint != record.fingerprint
+                {
+                    Some(Invalidation::By(String::from("task change")))
+                } else if existing.deps_digest != record.deps_digest {
+                    Some(Invalidation::By(String::from("dep change")))
+                } else {
+                    None
+                }
+            }
+            _ => None,
         }
     }
 
@@ -817,7 +1251,19 @@ impl<'a> Runner<'a> {
                         }
                     }
                 }
+                // running services are restarted
+                if let Some(cmd_num) = job.service_cmd {
+                    if matches!(
+                        task.chomp_task.watch_invalidation,
+                        Some(WatchInvalidation::SkipRunning)
+                    ) {
+                        return Ok(());
+                    }
+                    let display_name = job.display_name(&self.tasks, &self.cwd);
+                    self.cmd_pool.terminate(cmd_num, &display_name);
+                }
                 let job = self.get_job_mut(job_num).unwrap();
+                job.service_cmd = None;
                 job.state = JobState::Pending;
                 job
             }
@@ -838,9 +1284,13 @@ impl<'a> Runner<'a> {
                     }
                     self.cmd_pool.terminate(cmd_num, &display_name);
                 }
+                // a job waiting out its retry-delay or remote cache fetch starts over
+                self.retrying.remove(&job_num);
+                self.fetching.remove(&job_num);
                 let job = self.get_job_mut(job_num).unwrap();
                 job.mtime = Some(now() - Duration::from_secs(1));
                 job.state = JobState::Pending;
+                job.attempt = 0;
                 job
             }
             _ => self.get_job_mut(job_num).unwrap(),
@@ -872,18 +1322,56 @@ impl<'a> Runner<'a> {
                 }
                 Node::File(ref mut file) => {
                     file.mtime = Some(now());
-                    for parent in file.parents.clone() {
+                    let parents = file.parents.clone();
+                    self.reload_task_env(&path_str)?;
+                    for parent in parents {
                         self.invalidate_job(parent, queued, redrives)?;
                     }
                     Ok(true)
                 }
             },
-            None => Ok(false),
+            // an env file which did not exist when its tasks were expanded
+            None => {
+                let tasks: Vec<usize> = (0..self.tasks.len())
+                    .filter(|&task| self.tasks[task].env_files.contains(&path_str))
+                    .collect();
+                if tasks.is_empty() {
+                    return Ok(false);
+                }
+                self.reload_task_env(&path_str)?;
+                let jobs: Vec<usize> = (0..self.nodes.len())
+                    .filter(|&job_num| {
+                        self.get_job(job_num)
+                            .is_some_and(|job| job.live && tasks.contains(&job.task))
+                    })
+                    .collect();
+                for job_num in jobs {
+                    self.invalidate_job(job_num, queued, redrives)?;
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    // tasks using a changed env file rerun with its new variables
+    fn reload_task_env(&mut self, path: &str) -> Result<()> {
+        for task in self.tasks.iter_mut() {
+            if task.env_files.iter().any(|env_file| env_file == path) {
+                let chomp_task = task.chomp_task;
+                task.env = create_task_env(
+                    chomp_task,
+                    self.chompfile,
+                    &self.cwd,
+                    chomp_task.env_replace.unwrap_or(true),
+                )?;
+            }
         }
+        Ok(())
     }
 
     fn expand_job_deps(&self, job_num: usize, deps: &mut Vec<String>) {
         let job = self.get_job(job_num).unwrap();
+        let env_files = &self.tasks[job.task].env_files;
         for &dep in job.deps.iter() {
             match &self.nodes[dep] {
                 Node::Job(job) => {
@@ -903,6 +1391,9 @@ impl<'a> Runner<'a> {
                 }
                 Node::File(file) => {
                     let name = &file.name;
+                    if env_files.contains(name) {
+                        continue;
+                    }
                     if deps.iter().find(|&dep| dep == name).is_none() {
                         deps.push(name.to_string());
                     }
@@ -911,121 +1402,117 @@ impl<'a> Runner<'a> {
         }
     }
 
-    fn run_job(
-        &mut self,
-        job_num: usize,
-        force: bool,
-    ) -> Option<(usize, Pin<Box<dyn Future<Output = StateTransition> + 'a>>)> {
+    // make-style check of the job deps against the job target mtime
+    fn deps_invalidated(&self, job_num: usize, mtime: Duration) -> Option<Invalidation> {
         let job = self.get_job(job_num).unwrap();
-        if job.state != JobState::Pending {
-            panic!("Expected pending job");
-        }
-        let task = &self.tasks[job.task];
-        // CMD Exec
-        if task.chomp_task.run.is_none() {
-            self.mark_complete(job_num, Some(now()), None, false);
-            return None;
+        for &dep in job.deps.iter() {
+            match &self.nodes[dep] {
+                Node::Job(dep) => {
+                    let invalidated = match &self.tasks[dep.task]
+                        .chomp_task
+                        .invalidation
+                        .unwrap_or_default()
+                    {
+                        InvalidationCheck::NotFound
+                        | InvalidationCheck::Always
+                        | InvalidationCheck::Mtime
+                        | InvalidationCheck::Hash => match dep.mtime {
+                            Some(dep_mtime) => dep_mtime > mtime,
+                            None => true,
+                        },
+                    };
+                    if invalidated {
+                        return Some(Invalidation::By(dep.display_name(&self.tasks, &self.cwd)));
+                    }
+                }
+                Node::File(dep) => {
+                    let invalidated = match dep.mtime {
+                        Some(dep_mtime) => dep_mtime > mtime,
+                        None => true,
+                    };
+                    if invalidated {
+                        return Some(Invalidation::By(dep.name.to_string()));
+                    }
+                }
+            };
         }
-        // the interpolation template itself is not run
-        if job.interpolate.is_none() {
-            let has_interpolation = task.deps.iter().find(|&d| d.contains('#')).is_some();
-            if has_interpolation {
-                self.mark_complete(job_num, Some(now()), None, false);
-                return None;
+        None
+    }
+
+    // content check of the expanded job deps against the digests recorded on the last
+    // successful run, so that deps with a new mtime but the same contents do not invalidate
+    // a target whose contents differ from the digest recorded at its last build
+    fn targets_modified(
+        &self,
+        job_num: usize,
+        recorded: &BTreeMap<String, String>,
+    ) -> Option<Invalidation> {
+        let job = self.get_job(job_num).unwrap();
+        for target in job.targets.iter() {
+            let name = relative_path(target, &self.cwd);
+            if let Some(hash) = recorded.get(&name) {
+                if hash_file(target).unwrap_or_default() != *hash {
+                    return Some(Invalidation::TargetModified(name));
+                }
             }
         }
-        // If we have an mtime, check if we need to do work
-        if let Some(mtime) = job.mtime {
-            let can_skip = task.chomp_task.args.is_none()
-                && match task.chomp_task.invalidation.unwrap_or_default() {
-                    InvalidationCheck::NotFound => true,
-                    InvalidationCheck::Always => {
-                        if !force
-                            && (matches!(
-                                task.chomp_task.display,
-                                Some(TaskDisplay::InitStatus) | Some(TaskDisplay::InitOnly) | None
-                            ) || self.chompfile.echo)
-                        {
-                            println!(
-                                "  \x1b[1m{}\x1b[0m invalidated",
-                                job.display_name(&self.tasks, &self.cwd),
-                            );
-                        }
-                        false
-                    }
-                    InvalidationCheck::Mtime => {
-                        if force {
-                            false
-                        } else {
-                            let mut dep_change = false;
-                            for &dep in job.deps.iter() {
-                                dep_change = match &self.nodes[dep] {
-                                    Node::Job(dep) => {
-                                        let invalidated = match &self.tasks[dep.task]
-                                            .chomp_task
-                                            .invalidation
-                                            .unwrap_or_default()
-                                        {
-                                            InvalidationCheck::NotFound
-                                            | InvalidationCheck::Always
-                                            | InvalidationCheck::Mtime => match dep.mtime {
-                                                Some(dep_mtime) => dep_mtime > mtime,
-                                                None => true,
-                                            },
-                                        };
-                                        if invalidated
-                                            && (matches!(
-                                                task.chomp_task.display,
-                                                Some(TaskDisplay::InitStatus)
-                                                    | Some(TaskDisplay::InitOnly)
-                                                    | None
-                                            ) || self.chompfile.echo)
-                                        {
-                                            println!(
-                                                "  \x1b[1m{}\x1b[0m invalidated by {}",
-                                                job.display_name(&self.tasks, &self.cwd),
-                                                dep.display_name(&self.tasks, &self.cwd)
-                                            );
-                                        }
-                                        invalidated
-                                    }
-                                    Node::File(dep) => {
-                                        let invalidated = match dep.mtime {
-                                            Some(dep_mtime) => dep_mtime > mtime,
-                                            None => true,
-                                        };
-                                        if invalidated
-                                            && (matches!(
-                                                task.chomp_task.display,
-                                                Some(TaskDisplay::InitStatus)
-                                                    | Some(TaskDisplay::InitOnly)
-                                                    | None
-                                            ) || self.chompfile.echo)
-                                        {
-                                            println!(
-                                                "  \x1b[1m{}\x1b[0m invalidated by {}",
-                                                job.display_name(&self.tasks, &self.cwd),
-                                                dep.name
-                                            );
-                                        }
-                                        invalidated
-                                    }
-                                };
-                                if dep_change {
-                                    break;
-                                }
-                            }
-                            !dep_change
-                        }
-                    }
-                };
-            if can_skip {
-                self.mark_complete(job_num, None, None, false);
-                return None;
+        None
+    }
+
+    fn dep_hashes_invalidated(
+        &self,
+        job_num: usize,
+        mtime: Duration,
+        recorded: &BTreeMap<String, String>,
+    ) -> Option<Invalidation> {
+        let job = self.get_job(job_num).unwrap();
+        // task deps without targets have nothing to hash, so are compared by mtime
+        for &dep in job.deps.iter() {
+            if let Node::Job(dep) = &self.nodes[dep] {
+                if !dep.targets.is_empty() || self.is_interpolation_template(dep) {
+                    continue;
+                }
+                if dep.mtime.is_none_or(|dep_mtime| dep_mtime > mtime) {
+                    return Some(Invalidation::By(dep.display_name(&self.tasks, &self.cwd)));
+                }
             }
         }
+        let hashes = self.dep_hashes(job_num);
+        if &hashes == recorded {
+            return None;
+        }
+        Some(Invalidation::By(
+            match hashes
+                .iter()
+                .find(|(dep, hash)| recorded.get(*dep) != Some(hash))
+            {
+                Some((dep, _)) => dep.to_string(),
+                None => String::from("dep change"),
+            },
+        ))
+    }
 
-        let run = task.chomp_task.run.as_ref().unwrap();
+    fn dep_hashes(&self, job_num: usize) -> BTreeMap<String, String> {
+        let mut deps = Vec::new();
+        self.expand_job_deps(job_num, &mut deps);
+        deps.iter()
+            .map(|dep| {
+                (
+                    relative_path(dep, &self.cwd),
+                    hash_file(dep).unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+
+    fn is_interpolation_template(&self, job: &Job) -> bool {
+        job.interpolate.is_none() && self.tasks[job.task].deps.iter().any(|d| d.contains('#'))
+    }
+
+    // the execution environment of a job, along with its sorted relative deps
+    fn job_env(&self, job_num: usize) -> (BTreeMap<String, String>, Vec<String>) {
+        let job = self.get_job(job_num).unwrap();
+        let task = &self.tasks[job.task];
         let mut env = task.env.clone();
         if let Some(interpolate) = &job.interpolate {
             env.insert("MATCH".to_string(), interpolate.to_string());
@@ -1096,46 +1583,468 @@ impl<'a> Runner<'a> {
         };
         env.insert("TARGETS".to_string(), relative_targets);
 
-        // relative dep for backward compatibility
-        let relative_dep = match deps.first() {
-            Some(first_dep) => relative_path(first_dep, &self.cwd),
-            None => String::new(),
-        };
-        env.insert("DEP".to_string(), relative_dep);
+        // relative dep for backward compatibility
+        let relative_dep = match deps.first() {
+            Some(first_dep) => relative_path(first_dep, &self.cwd),
+            None => String::new(),
+        };
+        env.insert("DEP".to_string(), relative_dep);
+
+        let mut relative_deps = deps
+            .iter()
+            .map(|d| relative_path(d, &self.cwd))
+            .collect::<Vec<String>>();
+        relative_deps.sort();
+        env.insert("DEPS".to_string(), relative_deps.join(":"));
+
+        if let Some(args) = task.chomp_task.args.as_ref() {
+            for arg in args {
+                let k = arg.to_uppercase();
+                env.entry(k).or_insert_with(|| String::from(""));
+            }
+        }
+
+        (env, relative_deps)
+    }
+
+    // the reason a pending job needs to run, or None if it is fresh
+    fn job_invalidation(
+        &self,
+        job_num: usize,
+        force: bool,
+        record: &JobRecord,
+    ) -> Option<Invalidation> {
+        let job = self.get_job(job_num).unwrap();
+        let task = &self.tasks[job.task];
+        let mtime = match job.mtime {
+            Some(mtime) => mtime,
+            None if job.targets.is_empty() => return Some(Invalidation::NoTargets),
+            None => return Some(Invalidation::TargetMissing),
+        };
+        if task.chomp_task.args.is_some() {
+            return Some(Invalidation::Args);
+        }
+        match task.chomp_task.invalidation.unwrap_or_default() {
+            InvalidationCheck::NotFound => None,
+            _ if force => Some(Invalidation::Force),
+            InvalidationCheck::Always => Some(Invalidation::Always),
+            InvalidationCheck::Mtime => self
+                .cmd_changed(job_num, record)
+                .or_else(|| self.deps_invalidated(job_num, mtime)),
+            InvalidationCheck::Hash => self.cmd_changed(job_num, record).or_else(|| {
+                match self.state.get(&job.display_name(&self.tasks, &self.cwd)) {
+                    Some(record) => self
+                        .targets_modified(job_num, &record.target_hashes)
+                        .or_else(|| {
+                            self.dep_hashes_invalidated(job_num, mtime, &record.dep_hashes)
+                        }),
+                    // no digests recorded yet -> fall back to the mtime check
+                    None => self.deps_invalidated(job_num, mtime),
+                }
+            }),
+        }
+    }
+
+    // returns the job future with its batched command, or without a command while the
+    // targets are fetched from the remote cache
+    fn run_job(
+        &mut self,
+        job_num: usize,
+        force: bool,
+    ) -> Option<(
+        Option<usize>,
+        Pin<Box<dyn Future<Output = StateTransition> + 'a>>,
+    )> {
+        let job = self.get_job(job_num).unwrap();
+        if job.state != JobState::Pending {
+            panic!("Expected pending job");
+        }
+        let task = &self.tasks[job.task];
+        // CMD Exec
+        if task.chomp_task.run.is_none() {
+            self.mark_complete(job_num, Some(now()), None, false);
+            return None;
+        }
+        // the interpolation template itself is not run
+        if job.interpolate.is_none() {
+            let has_interpolation = task.deps.iter().find(|&d| d.contains('#')).is_some();
+            if has_interpolation {
+                self.mark_complete(job_num, Some(now()), None, false);
+                return None;
+            }
+        }
+        let (env, deps) = self.job_env(job_num);
+        if let Some(condition) = &task.chomp_task.condition {
+            if let Some(reason) =
+                condition_unmet(condition, &env, job.interpolate.as_ref(), &self.cwd)
+            {
+                self.get_job_mut(job_num).unwrap().unmet = Some(reason);
+                self.mark_complete(job_num, None, None, false);
+                return None;
+            }
+        }
+        let run = task.chomp_task.run.as_ref().unwrap();
+        let engine = task.chomp_task.engine.unwrap_or_default();
+        let env_replace = task.chomp_task.env_replace.unwrap_or(true);
+        let resolved_run = if matches!(engine, ChompEngine::Shell) && env_replace {
+            replace_env_vars_static(run, &env)
+        } else {
+            run.to_string()
+        };
+        let record = JobRecord {
+            fingerprint: Some(task.fingerprint.clone()),
+            cmd_digest: Some(cmd_digest(&resolved_run, &env)),
+            deps_digest: Some(deps_digest(&deps)),
+            ..Default::default()
+        };
+        // If we have an mtime, check if we need to do work
+        let invalidation = self.job_invalidation(job_num, force, &record);
+        if self.explain {
+            let mtime = self.get_job(job_num).unwrap().mtime;
+            self.explanations
+                .insert(job_num, (mtime, invalidation.clone()));
+        }
+        let job = self.get_job(job_num).unwrap();
+        let task = &self.tasks[job.task];
+        let invalidation = match invalidation {
+            Some(invalidation) => invalidation,
+            None => {
+                self.get_job_mut(job_num).unwrap().record = Some(Box::new(record));
+                self.mark_complete(job_num, None, None, false);
+                return None;
+            }
+        };
+        if self.reporter.is_json() {
+            self.reporter.report(Event::Invalidated {
+                job: &job.display_name(&self.tasks, &self.cwd),
+                reason: invalidation.to_string(),
+            });
+        }
+        if self.dry_run {
+            if !self.explain && !self.reporter.is_json() {
+                println!(
+                    "\x1b[1m▷\x1b[0m {} \x1b[34m[{}]\x1b[0m",
+                    job.display_name(&self.tasks, &self.cwd),
+                    invalidation
+                );
+            }
+            // treated as freshly built so that parents are invalidated in turn
+            let job = self.get_job_mut(job_num).unwrap();
+            job.mtime = Some(now());
+            job.state = JobState::Fresh;
+            return None;
+        }
+        if matches!(
+            invalidation,
+            Invalidation::Always | Invalidation::By(_) | Invalidation::TargetModified(_)
+        ) && !self.reporter.is_json()
+            && (matches!(
+                task.chomp_task.display,
+                Some(TaskDisplay::InitStatus) | Some(TaskDisplay::InitOnly) | None
+            ) || self.chompfile.echo)
+        {
+            match invalidation {
+                Invalidation::By(dep) => ui::println(&format!(
+                    "  \x1b[1m{}\x1b[0m invalidated by {}",
+                    job.display_name(&self.tasks, &self.cwd),
+                    dep
+                )),
+                Invalidation::TargetModified(target) => ui::println(&format!(
+                    "  \x1b[1m{}\x1b[0m invalidated as {} was modified",
+                    job.display_name(&self.tasks, &self.cwd),
+                    target
+                )),
+                _ => ui::println(&format!(
+                    "  \x1b[1m{}\x1b[0m invalidated",
+                    job.display_name(&self.tasks, &self.cwd),
+                )),
+            }
+        }
+        // restore the targets from the artifact cache instead of executing on a hit
+        let cache_key = if !force
+            && task.chomp_task.cache.unwrap_or(false)
+            && !matches!(
+                task.chomp_task.invalidation,
+                Some(InvalidationCheck::Always)
+            )
+            && task.chomp_task.args.is_none()
+            && !job.targets.is_empty()
+        {
+            Some(artifact_key(
+                &resolved_run,
+                &env,
+                engine,
+                task.chomp_task.cwd.as_deref(),
+                &self.dep_hashes(job_num),
+            ))
+        } else {
+            None
+        };
+        let mut fetch = None;
+        if let Some(key) = &cache_key {
+            match restore_artifacts(key, &job.targets) {
+                Ok(true) => {
+                    let job = self.get_job_mut(job_num).unwrap();
+                    job.record = Some(Box::new(record));
+                    job.restored = true;
+                    self.mark_complete(job_num, Some(now()), None, false);
+                    return None;
+                }
+                Ok(false) => {
+                    fetch = self
+                        .chompfile
+                        .cache
+                        .remote
+                        .clone()
+                        .map(|remote| (remote, key.to_string(), job.targets.clone()));
+                }
+                Err(e) => ui::eprintln(&format!(
+                    "Unable to restore {} from the artifact cache: {}",
+                    job.display_name(&self.tasks, &self.cwd),
+                    e
+                )),
+            }
+        }
+        let name = job.display_name(&self.tasks, &self.cwd);
+        let job = self.get_job_mut(job_num).unwrap();
+        job.record = Some(Box::new(record));
+        job.cache_key = cache_key;
+        // on a local cache miss the remote cache is fetched in the job future, completing
+        // through the pending state to restore the targets or batch the command
+        if let Some((remote, key, targets)) = fetch {
+            let hit = Rc::new(Cell::new(false));
+            self.fetching.insert(job_num, hit.clone());
+            let future = async move {
+                match restore_remote_artifacts(remote, key, targets).await {
+                    Ok(restored) => hit.set(restored),
+                    Err(e) => ui::eprintln(&format!(
+                        "Unable to restore {} from the artifact cache: {}",
+                        name, e
+                    )),
+                }
+                StateTransition::from_job(job_num, JobState::Pending, None)
+            };
+            return Some((None, future.boxed_local()));
+        }
+        let (cmd_num, future) = self.batch_job(job_num, env);
+        Some((Some(cmd_num), future))
+    }
+
+    // a service that is ready completes while it continues running, with its exit tracked
+    // for the rest of the session
+    fn service_ready(
+        &mut self,
+        job_num: usize,
+        cmd_num: usize,
+        futures: &mut Vec<Pin<Box<dyn Future<Output = StateTransition> + 'a>>>,
+        queued: &mut QueuedStateTransitions,
+    ) {
+        self.get_job_mut(job_num).unwrap().service_cmd = Some(cmd_num);
+        self.mark_complete(job_num, Some(now()), None, false);
+        let exec_future = self.cmd_pool.get_exec_future(cmd_num);
+        if let Some(transition) = queued.insert_job(job_num, JobState::Fresh, Some(cmd_num)) {
+            futures.push(
+                async move {
+                    let _ = exec_future.await;
+                    transition
+                }
+                .boxed_local(),
+            );
+        }
+    }
+
+    fn running_services(&self) -> usize {
+        (0..self.nodes.len())
+            .filter_map(|job_num| self.get_job(job_num))
+            .filter(|job| job.service_cmd.is_some())
+            .count()
+    }
 
-        let mut relative_deps = deps
-            .iter()
-            .map(|d| relative_path(d, &self.cwd))
-            .collect::<Vec<String>>();
-        relative_deps.sort();
-        env.insert("DEPS".to_string(), relative_deps.join(":"));
+    // terminates the running services, whose exits are then no longer reported
+    fn stop_services(&mut self) {
+        for job_num in 0..self.nodes.len() {
+            let job = match self.get_job(job_num) {
+                Some(job) => job,
+                None => continue,
+            };
+            if let Some(cmd_num) = job.service_cmd {
+                let display_name = job.display_name(&self.tasks, &self.cwd);
+                self.cmd_pool.terminate(cmd_num, &display_name);
+                self.get_job_mut(job_num).unwrap().service_cmd = None;
+            }
+        }
+    }
 
-        if let Some(args) = task.chomp_task.args.as_ref() {
-            for arg in args {
-                let k = arg.to_uppercase();
-                env.entry(k).or_insert_with(|| String::from(""));
+    // terminates all running jobs for --fail-fast, ending the drive loop
+    fn cancel_running(&mut self) {
+        self.cancelled = true;
+        for job_num in 0..self.nodes.len() {
+            // jobs waiting out their retry-delay or remote cache fetch are not batched
+            if self.retrying.remove(&job_num) || self.fetching.remove(&job_num).is_some() {
+                let job = self.get_job_mut(job_num).unwrap();
+                job.state = JobState::Failed;
+                job.attempt = 0;
+                self.outcomes.insert(job_num, JobOutcome::Cancelled);
+                continue;
+            }
+            let job = match self.get_job(job_num) {
+                Some(job) => job,
+                None => continue,
+            };
+            if let (JobState::Running, Some(cmd_num)) = (job.state, job.cmd_num) {
+                let display_name = job.display_name(&self.tasks, &self.cwd);
+                self.cmd_pool.terminate(cmd_num, &display_name);
+                self.outcomes.insert(job_num, JobOutcome::Cancelled);
+            } else if let Some(cmd_num) = job.service_cmd {
+                let display_name = job.display_name(&self.tasks, &self.cwd);
+                self.cmd_pool.terminate(cmd_num, &display_name);
             }
         }
+    }
 
-        let targets = job.targets.clone();
+    // rebatches a failed job when it has retries remaining, returning false otherwise
+    fn retry_job(
+        &mut self,
+        job_num: usize,
+        futures: &mut Vec<Pin<Box<dyn Future<Output = StateTransition> + 'a>>>,
+        queued: &mut QueuedStateTransitions,
+    ) -> bool {
+        let job = self.get_job(job_num).unwrap();
+        let task = &self.tasks[job.task];
+        let retries = task.chomp_task.retries.unwrap_or(0);
+        if job.attempt >= retries {
+            return false;
+        }
+        let delay = task
+            .chomp_task
+            .retry_delay
+            .map(Duration::from_secs_f64)
+            .unwrap_or_default();
+        if self.reporter.is_json() {
+            self.reporter.report(Event::Retry {
+                job: &job.display_name(&self.tasks, &self.cwd),
+                attempt: job.attempt + 2,
+                attempts: retries + 1,
+            });
+        } else {
+            ui::println(&format!(
+                "\x1b[1;33m↻\x1b[0m {} \x1b[34m[retrying, attempt {} of {}]\x1b[0m",
+                job.display_name(&self.tasks, &self.cwd),
+                job.attempt + 2,
+                retries + 1
+            ));
+        }
+        let job = self.get_job_mut(job_num).unwrap();
+        job.attempt += 1;
+        job.cmd_num = None;
+        if delay.is_zero() {
+            let (env, _) = self.job_env(job_num);
+            let (cmd_num, future) = self.batch_job(job_num, env);
+            if queued
+                .insert_job(job_num, JobState::Running, Some(cmd_num))
+                .is_some()
+            {
+                futures.push(future);
+            }
+        } else if let Some(transition) = queued.insert_job(job_num, JobState::Pending, None) {
+            self.retrying.insert(job_num);
+            futures.push(
+                async move {
+                    time::sleep(delay).await;
+                    transition
+                }
+                .boxed_local(),
+            );
+        }
+        true
+    }
+
+    // The length of the longest chain of live jobs waiting on this job, so that
+    // commands on the critical path can be started first when the pool is full.
+    // Jobs count by their last recorded duration in milliseconds, or 1 if unknown.
+    fn critical_path(&self, job_num: usize, lengths: &mut HashMap<usize, usize>) -> usize {
+        if let Some(&length) = lengths.get(&job_num) {
+            return length;
+        }
+        // visited before recursing, so that cycles through the parents end here
+        lengths.insert(job_num, 0);
+        let job = self.get_job(job_num).unwrap();
+        let mut length = 0;
+        for &parent in job.parents.iter() {
+            if parent != job_num && self.get_job(parent).is_some_and(|parent| parent.live) {
+                length = length.max(self.critical_path(parent, lengths));
+            }
+        }
+        let duration = self
+            .state
+            .duration(&job.display_name(&self.tasks, &self.cwd))
+            .map(|duration| duration as usize)
+            .unwrap_or(0)
+            .max(1);
+        lengths.insert(job_num, length + duration);
+        length + duration
+    }
+
+    // batches the job command for execution, returning the running state transition future
+    fn batch_job(
+        &mut self,
+        job_num: usize,
+        env: BTreeMap<String, String>,
+    ) -> (usize, Pin<Box<dyn Future<Output = StateTransition> + 'a>>) {
+        let mut critical_paths = std::mem::take(&mut self.critical_paths);
+        let priority = self.critical_path(job_num, &mut critical_paths);
+        self.critical_paths = critical_paths;
+        let job = self.get_job(job_num).unwrap();
+        let task = &self.tasks[job.task];
+        let run = task.chomp_task.run.as_ref().unwrap();
         let engine = task.chomp_task.engine.unwrap_or_default();
         let env_replace = task.chomp_task.env_replace.unwrap_or(true);
+        let is_service = task.chomp_task.service.unwrap_or(false);
+        let ready = task.chomp_task.ready.clone().unwrap_or_default();
+        let output = task.chomp_task.output.unwrap_or_default();
+        // tasks inheriting the terminal keep it unless the progress display is shown,
+        // the JUnit report needs their stderr or the JSON reporter needs stdout for its
+        // events. Output is otherwise only teed through a pipe into the task log in
+        // watch mode and when Chomp is not writing to a terminal, where the log is the
+        // way to review it.
+        let capture = ui::is_active()
+            || self.cmd_pool.capture_stderr
+            || self.reporter.is_json()
+            || self.watch
+            || !std::io::stdout().is_terminal()
+            || !matches!(output, TaskOutput::Inherit)
+            || is_service && ready.log.is_some();
+
+        let targets = job.targets.clone();
         let echo = if let Some(echo) = task.chomp_task.echo {
             echo
         } else {
             self.chompfile.echo
         };
         let cmd_num = {
-            let stdio = task.chomp_task.stdio.unwrap_or_default();
+            // watched tasks rerun behind the watcher without reading from the terminal,
+            // so that they are also run in their own process group
+            let stdio = match task.chomp_task.stdio.unwrap_or_default() {
+                TaskStdio::All if self.watch => TaskStdio::NoStdin,
+                stdio => stdio,
+            };
             let display_name = if matches!(
                 task.chomp_task.display,
                 Some(TaskDisplay::InitStatus) | Some(TaskDisplay::InitOnly) | None
             ) || echo
+                || self.reporter.is_json()
+                || ui::is_active()
+                || matches!(output, TaskOutput::Prefixed)
             {
                 Some(job.display_name(&self.tasks, &self.cwd))
             } else {
                 None
             };
+            let log = if capture {
+                Some(job.display_name(&self.tasks, &self.cwd))
+            } else {
+                None
+            };
             let cwd = match &task.chomp_task.cwd {
                 Some(cwd) => {
                     let cwd_path = PathBuf::from(cwd);
@@ -1172,6 +2081,13 @@ impl<'a> Runner<'a> {
                 engine,
                 stdio,
                 echo,
+                task.chomp_task.timeout.map(Duration::from_secs_f64),
+                capture,
+                output,
+                log,
+                task.chomp_task.weight.unwrap_or(1),
+                task.chomp_task.resources.clone().unwrap_or_default(),
+                priority,
             );
             let job = self.get_job_mut(job_num).unwrap();
             job.state = JobState::Running;
@@ -1179,14 +2095,31 @@ impl<'a> Runner<'a> {
             cmd_num
         };
         let exec_future = self.cmd_pool.get_exec_future(cmd_num);
-        Some((
+        if !is_service {
+            return (
+                cmd_num,
+                async move {
+                    let _ = exec_future.await;
+                    StateTransition::from_job(job_num, JobState::Running, Some(cmd_num))
+                }
+                .boxed_local(),
+            );
+        }
+        // services complete once ready, or on exiting before becoming ready
+        let ready_future = self.cmd_pool.get_ready_future(
+            cmd_num,
+            ready.log.map(|log| Regex::new(&log).unwrap()),
+            ready.port,
+            ready.file.map(|file| resolve_path(&file, &self.cwd)),
+        );
+        (
             cmd_num,
             async move {
-                let _ = exec_future.await;
+                select(ready_future, exec_future).await;
                 StateTransition::from_job(job_num, JobState::Running, Some(cmd_num))
             }
             .boxed_local(),
-        ))
+        )
     }
 
     // top-down driver - initiates future starts
@@ -1210,8 +2143,10 @@ impl<'a> Runner<'a> {
                     if !job.live {
                         return Ok(JobOrFileState::Job(job.state));
                     }
-                } else {
+                } else if !job.live {
                     job.live = true;
+                    self.live_jobs += 1;
+                    self.critical_paths.clear();
                 }
                 match job.state {
                     JobState::Sentinel | JobState::Uninitialized => {
@@ -1254,6 +2189,7 @@ impl<'a> Runner<'a> {
                     }
                     JobState::Pending => {
                         let mut all_completed = true;
+                        let mut dep_failed = false;
                         let job = self.get_job(job_num).unwrap();
                         let serial = self.tasks[job.task].chomp_task.serial.unwrap_or_default();
                         let deps = job.deps.clone();
@@ -1276,18 +2212,11 @@ impl<'a> Runner<'a> {
                                 | JobOrFileState::File(FileState::Found) => {}
                                 JobOrFileState::Job(JobState::Failed)
                                 | JobOrFileState::File(FileState::NotFound) => {
-                                    self.mark_complete(job_num, None, None, true);
-                                    let transition = queued
-                                        .insert_job(job_num, JobState::Running, None)
-                                        .unwrap();
-                                    self.drive_completion(
-                                        transition,
-                                        force,
-                                        futures,
-                                        queued,
-                                        watch_listener.clone(),
-                                    )?;
-                                    return Ok(JobOrFileState::Job(JobState::Failed));
+                                    dep_failed = true;
+                                    // keep going still drives the remaining deps
+                                    if !self.keep_going {
+                                        break;
+                                    }
                                 }
                                 _ => {
                                     // Serial only proceeds on a completion result
@@ -1305,15 +2234,33 @@ impl<'a> Runner<'a> {
                             return Ok(JobOrFileState::Job(job.state));
                         }
 
+                        if dep_failed {
+                            self.mark_complete(job_num, None, None, true);
+                            let transition =
+                                queued.insert_job(job_num, JobState::Running, None).unwrap();
+                            self.drive_completion(
+                                transition,
+                                force,
+                                futures,
+                                queued,
+                                watch_listener.clone(),
+                            )?;
+                            return Ok(JobOrFileState::Job(JobState::Failed));
+                        }
+
                         // deps all completed -> execute this job
                         if all_completed {
                             return match self.run_job(job_num, force) {
                                 Some((cmd_num, future)) => {
-                                    if queued.insert_job(
-                                        job_num,
-                                        JobState::Running,
-                                        Some(cmd_num),
-                                    ).is_some() { futures.push(future) };
+                                    // remote cache fetches complete through the pending state
+                                    let state = if cmd_num.is_some() {
+                                        JobState::Running
+                                    } else {
+                                        JobState::Pending
+                                    };
+                                    if queued.insert_job(job_num, state, cmd_num).is_some() {
+                                        futures.push(future)
+                                    };
                                     Ok(JobOrFileState::Job(JobState::Running))
                                 }
                                 None => {
@@ -1453,13 +2400,19 @@ impl<'a> Runner<'a> {
                     .chomp_task
                     .validation
                     .unwrap_or_default();
-                if let Some(cmd_num) = job.cmd_num {
+                let service_cmd = job.cmd_num.filter(|&cmd_num| {
+                    self.tasks[job.task].chomp_task.service.unwrap_or(false)
+                        && self.cmd_pool.is_executing(cmd_num)
+                });
+                if let Some(cmd_num) = service_cmd {
+                    self.service_ready(node_num, cmd_num, futures, queued);
+                } else if let Some(cmd_num) = job.cmd_num {
                     let exec_future = self.cmd_pool.get_exec_future(cmd_num);
                     let (status, mtime, cmd_time) = match executor::block_on(exec_future) {
                         Ok(result) => result,
                         Err(err) => return Err(anyhow!("Exec error: {:?}", err)),
                     };
-                    match status {
+                    let failed = match status {
                         ExecState::Completed => {
                             let job = self.get_job(node_num).unwrap();
                             for target in &job.targets {
@@ -1472,36 +2425,40 @@ impl<'a> Runner<'a> {
                                     .send(path)
                                     .expect("Unable to send watcher event to server channel");
                             }
-                            self.mark_complete(
-                                node_num,
-                                mtime,
-                                Some(cmd_time),
-                                matches!(validation, ValidationCheck::NotOk)
-                                    || matches!(
-                                        validation,
-                                        ValidationCheck::TargetsOnly | ValidationCheck::OkTargets
-                                    ) && mtime.is_none(),
-                            );
+                            matches!(validation, ValidationCheck::NotOk)
+                                || matches!(
+                                    validation,
+                                    ValidationCheck::TargetsOnly | ValidationCheck::OkTargets
+                                ) && mtime.is_none()
                         }
                         ExecState::Failed => match validation {
-                            ValidationCheck::NotOk => {
-                                self.mark_complete(node_num, mtime, Some(cmd_time), false)
-                            }
-                            ValidationCheck::OkOnly | ValidationCheck::OkTargets => {
-                                self.mark_complete(node_num, mtime, Some(cmd_time), true)
+                            ValidationCheck::NotOk => false,
+                            ValidationCheck::OkOnly | ValidationCheck::OkTargets => true,
+                            ValidationCheck::None | ValidationCheck::TargetsOnly => {
+                                matches!(validation, ValidationCheck::TargetsOnly)
+                                    && mtime.is_none()
                             }
-                            ValidationCheck::None | ValidationCheck::TargetsOnly => self
-                                .mark_complete(
-                                    node_num,
-                                    mtime,
-                                    Some(cmd_time),
-                                    matches!(validation, ValidationCheck::TargetsOnly)
-                                        && mtime.is_none(),
-                                ),
                         },
+                        ExecState::TimedOut => {
+                            let job = self.get_job(node_num).unwrap();
+                            // batched execs are timed out together, by the shortest timeout
+                            ui::eprintln(&format!(
+                                "\x1b[1;31mTimeout:\x1b[0m {} exceeded its timeout and was terminated.",
+                                job.display_name(&self.tasks, &self.cwd)
+                            ));
+                            true
+                        }
                         ExecState::Terminated => return Ok(()),
                         _ => panic!("Unexpected promise exec state"),
                     };
+                    if failed && self.retry_job(node_num, futures, queued) {
+                        return Ok(());
+                    }
+                    self.mark_complete(node_num, mtime, Some(cmd_time), failed);
+                    if failed && self.fail_fast {
+                        self.cancel_running();
+                        return Ok(());
+                    }
                 }
                 let job = self.get_job(node_num).unwrap();
                 if matches!(job.state, JobState::Fresh | JobState::Failed) {
@@ -1518,6 +2475,88 @@ impl<'a> Runner<'a> {
                 }
                 Ok(())
             }
+            JobOrFileState::Job(JobState::Fresh) => {
+                // service exit, unless the service was already restarted
+                let job = self.get_job(node_num).unwrap();
+                let cmd_num = match job.service_cmd {
+                    Some(cmd_num) if transition.cmd_num == Some(cmd_num) => cmd_num,
+                    _ => return Ok(()),
+                };
+                let name = job.display_name(&self.tasks, &self.cwd);
+                let exec_future = self.cmd_pool.get_exec_future(cmd_num);
+                let (status, _, _) = match executor::block_on(exec_future) {
+                    Ok(result) => result,
+                    Err(err) => return Err(anyhow!("Exec error: {:?}", err)),
+                };
+                let success = matches!(status, ExecState::Completed);
+                if self.reporter.is_json() {
+                    self.reporter.report(Event::ServiceExit {
+                        job: &name,
+                        success,
+                    });
+                } else if success {
+                    ui::println(&format!(
+                        "\x1b[1m●\x1b[0m {} \x1b[34m[service exited]\x1b[0m",
+                        name
+                    ));
+                } else {
+                    ui::println(&format!(
+                        "\x1b[1;31mx\x1b[0m {} \x1b[34m[service exited]\x1b[0m",
+                        name
+                    ));
+                }
+                self.get_job_mut(node_num).unwrap().service_cmd = None;
+                Ok(())
+            }
+            JobOrFileState::Job(JobState::Pending) => {
+                // completed remote cache fetch, unless the job was invalidated or cancelled
+                // in the mean time
+                if let Some(hit) = self.fetching.remove(&node_num) {
+                    let job = self.get_job(node_num).unwrap();
+                    if !matches!(job.state, JobState::Running) || job.cmd_num.is_some() {
+                        return Ok(());
+                    }
+                    if hit.get() {
+                        let job = self.get_job_mut(node_num).unwrap();
+                        job.cache_key = None;
+                        job.restored = true;
+                        self.mark_complete(node_num, Some(now()), None, false);
+                        let transition = queued
+                            .insert_job(node_num, JobState::Running, None)
+                            .unwrap();
+                        return self.drive_completion(
+                            transition,
+                            force,
+                            futures,
+                            queued,
+                            watch_listener,
+                        );
+                    }
+                    let (env, _) = self.job_env(node_num);
+                    let (cmd_num, future) = self.batch_job(node_num, env);
+                    if queued
+                        .insert_job(node_num, JobState::Running, Some(cmd_num))
+                        .is_some()
+                    {
+                        futures.push(future);
+                    }
+                    return Ok(());
+                }
+                // delayed retry, unless the job was invalidated or cancelled in the mean time
+                let retrying = self.retrying.remove(&node_num);
+                let job = self.get_job(node_num).unwrap();
+                if retrying && matches!(job.state, JobState::Running) && job.cmd_num.is_none() {
+                    let (env, _) = self.job_env(node_num);
+                    let (cmd_num, future) = self.batch_job(node_num, env);
+                    if queued
+                        .insert_job(node_num, JobState::Running, Some(cmd_num))
+                        .is_some()
+                    {
+                        futures.push(future);
+                    }
+                }
+                Ok(())
+            }
             JobOrFileState::File(FileState::Checking) => {
                 let file = match self.nodes[node_num] {
                     Node::File(ref mut file) => file,
@@ -1890,6 +2929,333 @@ impl<'a> Runner<'a> {
         Ok(())
     }
 
+    // lists the jobs that ran, failed or were skipped, with a count of the cached jobs
+    fn print_summary(&self) {
+        let mut succeeded = Vec::new();
+        let mut failed = Vec::new();
+        let mut skipped = Vec::new();
+        let mut cancelled = Vec::new();
+        let mut unmet = Vec::new();
+        let mut cached = 0;
+        for (&job_num, outcome) in &self.outcomes {
+            let name = self
+                .get_job(job_num)
+                .unwrap()
+                .display_name(&self.tasks, &self.cwd);
+            match outcome {
+                JobOutcome::Succeeded => succeeded.push(name),
+                JobOutcome::Failed => failed.push(name),
+                JobOutcome::Skipped => skipped.push(name),
+                JobOutcome::Cancelled => cancelled.push(name),
+                JobOutcome::Unmet => unmet.push(name),
+                JobOutcome::Cached => cached += 1,
+            }
+        }
+        if self.reporter.is_json() {
+            self.reporter.report(Event::Summary {
+                succeeded,
+                failed,
+                skipped,
+                cancelled,
+                unmet,
+                cached,
+            });
+            return;
+        }
+        println!("\n\x1b[1mSummary\x1b[0m");
+        for name in &succeeded {
+            println!("  \x1b[1;32m√\x1b[0m {}", name);
+        }
+        for name in &failed {
+            println!("  \x1b[1;31mx\x1b[0m {}", name);
+        }
+        for name in &skipped {
+            println!("  \x1b[1;33m-\x1b[0m {} \x1b[34m[skipped]\x1b[0m", name);
+        }
+        for name in &cancelled {
+            println!("  \x1b[1;33m-\x1b[0m {} \x1b[34m[cancelled]\x1b[0m", name);
+        }
+        for name in &unmet {
+            println!(
+                "  \x1b[1m○\x1b[0m {} \x1b[34m[condition not met]\x1b[0m",
+                name
+            );
+        }
+        let mut counts = format!(
+            "{} succeeded, {} failed, {} skipped",
+            succeeded.len(),
+            failed.len(),
+            skipped.len()
+        );
+        if !cancelled.is_empty() {
+            counts.push_str(&format!(", {} cancelled", cancelled.len()));
+        }
+        if !unmet.is_empty() {
+            counts.push_str(&format!(", {} condition not met", unmet.len()));
+        }
+        println!("  {}, {} cached", counts, cached);
+    }
+
+    // one test case per job, with cached jobs and jobs that did not run as skipped
+    fn write_junit(&self, path: &str) -> Result<()> {
+        let timings: HashMap<usize, Duration> = self.timings.iter().copied().collect();
+        let cases: Vec<TestCase> = self
+            .outcomes
+            .iter()
+            .map(|(job_num, outcome)| TestCase {
+                name: self
+                    .get_job(*job_num)
+                    .unwrap()
+                    .display_name(&self.tasks, &self.cwd),
+                time: timings.get(job_num).copied().unwrap_or_default(),
+                result: match outcome {
+                    JobOutcome::Succeeded => TestResult::Passed,
+                    JobOutcome::Failed => TestResult::Failed(
+                        self.failure_output
+                            .get(job_num)
+                            .cloned()
+                            .unwrap_or_default(),
+                    ),
+                    JobOutcome::Cached => TestResult::Skipped("cached"),
+                    JobOutcome::Skipped => TestResult::Skipped("dependency failed"),
+                    JobOutcome::Cancelled => TestResult::Skipped("cancelled"),
+                    JobOutcome::Unmet => TestResult::Skipped("condition not met"),
+                },
+            })
+            .collect();
+        write_junit(path, &cases)
+    }
+
+    // the slowest jobs of the run, the total time per task and the parallelism achieved
+    fn print_timings(&self, run_time: Duration) {
+        let mut timings: Vec<(String, Duration)> = self
+            .timings
+            .iter()
+            .map(|&(job_num, cmd_time)| {
+                let name = self
+                    .get_job(job_num)
+                    .unwrap()
+                    .display_name(&self.tasks, &self.cwd);
+                (name, cmd_time)
+            })
+            .collect();
+        timings.sort_by_key(|(_, cmd_time)| Reverse(*cmd_time));
+        let mut task_totals: BTreeMap<String, (usize, Duration)> = BTreeMap::new();
+        for &(job_num, cmd_time) in &self.timings {
+            let job = self.get_job(job_num).unwrap();
+            let name = match &self.tasks[job.task].name {
+                Some(name) => name.to_string(),
+                None => job.display_name(&self.tasks, &self.cwd),
+            };
+            let total = task_totals.entry(name).or_insert((0, Duration::ZERO));
+            total.0 += 1;
+            total.1 += cmd_time;
+        }
+        let mut task_totals: Vec<(String, (usize, Duration))> = task_totals.into_iter().collect();
+        task_totals.sort_by_key(|(_, (_, total))| Reverse(*total));
+        let total: Duration = self.timings.iter().map(|(_, cmd_time)| *cmd_time).sum();
+        // cached jobs are listed with the duration recorded for their last run
+        let mut cached: Vec<(String, Duration)> = self
+            .outcomes
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, JobOutcome::Cached))
+            .filter_map(|(&job_num, _)| {
+                let name = self
+                    .get_job(job_num)
+                    .unwrap()
+                    .display_name(&self.tasks, &self.cwd);
+                let duration = self.state.duration(&name)?;
+                Some((name, Duration::from_secs_f64(duration / 1000.0)))
+            })
+            .collect();
+        cached.sort_by_key(|(_, duration)| Reverse(*duration));
+
+        println!("\n\x1b[1mTimings\x1b[0m");
+        if timings.is_empty() && cached.is_empty() {
+            println!("  No tasks were run.");
+            return;
+        }
+        if !timings.is_empty() {
+            println!("  Slowest jobs:");
+            for (name, cmd_time) in timings.iter().take(TIMINGS_SLOWEST) {
+                println!("    {} \x1b[34m[{:?}]\x1b[0m", name, cmd_time);
+            }
+            println!("  Task totals:");
+            for (name, (count, total)) in &task_totals {
+                println!(
+                    "    {} \x1b[34m[{:?} over {} run{}]\x1b[0m",
+                    name,
+                    total,
+                    count,
+                    if *count == 1 { "" } else { "s" }
+                );
+            }
+        }
+        if !cached.is_empty() {
+            println!("  Slowest cached jobs, as last run:");
+            for (name, duration) in cached.iter().take(TIMINGS_SLOWEST) {
+                println!("    {} \x1b[34m[{:?}]\x1b[0m", name, duration);
+            }
+        }
+        if !timings.is_empty() {
+            println!(
+                "  {:?} of task time in {:?}, {:.2}x parallelism",
+                total,
+                run_time,
+                total.as_secs_f64() / run_time.as_secs_f64()
+            );
+        }
+        if !cached.is_empty() {
+            let saved: Duration = cached.iter().map(|(_, duration)| *duration).sum();
+            println!(
+                "  {:?} of task time saved by {} cached job{}",
+                saved,
+                cached.len(),
+                if cached.len() == 1 { "" } else { "s" }
+            );
+        }
+    }
+
+    // prints the dep tree of a node with the freshness decision of each job, comparing
+    // file dep mtimes against the target mtime of the parent job
+    fn explain_node(
+        &self,
+        node: usize,
+        depth: usize,
+        parent_mtime: Option<Duration>,
+        visited: &mut HashSet<usize>,
+    ) {
+        let indent = "  ".repeat(depth);
+        match &self.nodes[node] {
+            Node::File(file) => {
+                let relation = match (file.mtime, parent_mtime) {
+                    (None, _) => String::from(" \x1b[31m[not found]\x1b[0m"),
+                    (Some(mtime), Some(parent_mtime)) if mtime > parent_mtime => format!(
+                        " \x1b[33m[modified {:?} after the target]\x1b[0m",
+                        mtime - parent_mtime
+                    ),
+                    (Some(_), _) => String::new(),
+                };
+                println!(
+                    "{}  {}{}",
+                    indent,
+                    relative_path(&file.name, &self.cwd),
+                    relation
+                );
+            }
+            Node::Job(job) => {
+                let name = job.display_name(&self.tasks, &self.cwd);
+                let (mtime, invalidation) = match self.explanations.get(&node) {
+                    Some((mtime, invalidation)) => (*mtime, invalidation.as_ref()),
+                    None => (None, None),
+                };
+                match invalidation {
+                    Some(invalidation) => println!(
+                        "{}\x1b[1m▷\x1b[0m {} \x1b[34m[{}]\x1b[0m",
+                        indent, name, invalidation
+                    ),
+                    None if self.explanations.contains_key(&node) => {
+                        println!("{}\x1b[1m●\x1b[0m {} \x1b[34m[cached]\x1b[0m", indent, name)
+                    }
+                    None => println!("{}\x1b[1m●\x1b[0m {}", indent, name),
+                }
+                if !visited.insert(node) {
+                    if !job.deps.is_empty() {
+                        println!("{}    ...", indent);
+                    }
+                    return;
+                }
+                for &dep in job.deps.iter() {
+                    if dep != node {
+                        self.explain_node(dep, depth + 1, mtime, visited);
+                    }
+                }
+            }
+        }
+    }
+
+    // prints the expanded graph reachable from the given jobs, with edges from each
+    // job to its deps
+    fn print_graph(&self, job_nums: &HashSet<usize>, format: GraphFormat) -> Result<()> {
+        let mut nodes: Vec<usize> = Vec::new();
+        let mut seen: HashSet<usize> = HashSet::new();
+        let mut stack: Vec<usize> = job_nums.iter().copied().collect();
+        stack.sort_unstable();
+        while let Some(node) = stack.pop() {
+            if !seen.insert(node) {
+                continue;
+            }
+            nodes.push(node);
+            if let Node::Job(job) = &self.nodes[node] {
+                stack.extend(job.deps.iter().rev());
+            }
+        }
+        nodes.sort_unstable();
+        let deps = |node: usize| match &self.nodes[node] {
+            Node::Job(job) => job.deps.clone(),
+            Node::File(_) => Vec::new(),
+        };
+        let name = |node: usize| match &self.nodes[node] {
+            Node::Job(job) => job.display_name(&self.tasks, &self.cwd),
+            Node::File(file) => relative_path(&file.name, &self.cwd),
+        };
+        match format {
+            GraphFormat::Dot => {
+                println!("digraph chomp {{");
+                for &node in &nodes {
+                    let shape = match &self.nodes[node] {
+                        Node::Job(_) => "box",
+                        Node::File(_) => "ellipse",
+                    };
+                    println!("  n{} [label={:?}, shape={}];", node, name(node), shape);
+                }
+                for &node in &nodes {
+                    for dep in deps(node) {
+                        println!("  n{} -> n{};", node, dep);
+                    }
+                }
+                println!("}}");
+            }
+            GraphFormat::Json => {
+                let mut roots: Vec<usize> = job_nums.iter().copied().collect();
+                roots.sort_unstable();
+                let nodes: Vec<serde_json::Value> = nodes
+                    .iter()
+                    .map(|&node| match &self.nodes[node] {
+                        Node::Job(job) => {
+                            let targets: Vec<String> = job
+                                .targets
+                                .iter()
+                                .map(|target| relative_path(target, &self.cwd))
+                                .collect();
+                            serde_json::json!({
+                                "id": node,
+                                "type": "job",
+                                "name": name(node),
+                                "task": self.tasks[job.task].name,
+                                "targets": targets,
+                                "deps": job.deps,
+                            })
+                        }
+                        Node::File(_) => serde_json::json!({
+                            "id": node,
+                            "type": "file",
+                            "name": name(node),
+                        }),
+                    })
+                    .collect();
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&serde_json::json!({
+                        "targets": roots,
+                        "nodes": nodes,
+                    }))?
+                );
+            }
+        }
+        Ok(())
+    }
+
     fn node_display(&self, node: usize) -> String {
         match &self.nodes[node] {
             Node::Job(j) => j.display_name(&self.tasks, &self.cwd),
@@ -1960,6 +3326,77 @@ impl<'a> Runner<'a> {
                     }
                     job_targets.push(target.to_string());
                 }
+                if let Some(timeout) = task.chomp_task.timeout {
+                    // also rejects values too large to be a Duration
+                    if timeout <= 0.0 || Duration::try_from_secs_f64(timeout).is_err() {
+                        return Err(anyhow!(
+                            "Invalid task {} - timeout must be a positive number of seconds.",
+                            &display_name
+                        ));
+                    }
+                }
+                if task.chomp_task.service.unwrap_or(false)
+                    && matches!(task.chomp_task.output, Some(TaskOutput::Grouped))
+                {
+                    return Err(anyhow!(
+                        "Invalid task {} - service tasks cannot use grouped output.",
+                        &display_name
+                    ));
+                }
+                if let Some(os) = task
+                    .chomp_task
+                    .condition
+                    .as_ref()
+                    .and_then(|condition| condition.os.as_ref())
+                {
+                    if !matches!(os.as_str(), "linux" | "macos" | "windows") {
+                        return Err(anyhow!(
+                            "Invalid task {} - condition os must be one of \"linux\", \"macos\" or \"windows\".",
+                            &display_name
+                        ));
+                    }
+                }
+                if let Some(ready) = &task.chomp_task.ready {
+                    if !task.chomp_task.service.unwrap_or(false) {
+                        return Err(anyhow!(
+                            "Invalid task {} - ready conditions only apply to service tasks.",
+                            &display_name
+                        ));
+                    }
+                    if let Some(Err(e)) = ready.log.as_ref().map(|log| Regex::new(log)) {
+                        return Err(anyhow!(
+                            "Invalid task {} - invalid ready log pattern: {}",
+                            &display_name,
+                            e
+                        ));
+                    }
+                }
+                if task.chomp_task.weight == Some(0) {
+                    return Err(anyhow!(
+                        "Invalid task {} - weight must be at least 1.",
+                        &display_name
+                    ));
+                }
+                for resource in task.chomp_task.resources.iter().flatten() {
+                    match self.chompfile.resources.get(resource) {
+                        Some(0) | None => {
+                            return Err(anyhow!(
+                                "Invalid task {} - resource '{}' must be declared in the Chompfile resources with a capacity of at least 1.",
+                                &display_name,
+                                resource
+                            ));
+                        }
+                        Some(_) => {}
+                    }
+                }
+                if let Some(retry_delay) = task.chomp_task.retry_delay {
+                    if Duration::try_from_secs_f64(retry_delay).is_err() {
+                        return Err(anyhow!(
+                            "Invalid task {} - retry-delay must be a non-negative number of seconds.",
+                            &display_name
+                        ));
+                    }
+                }
                 if task.chomp_task.args.is_some() && is_interpolate.is_some() {
                     return Err(anyhow!(
                         "Invalid task {} - cannot apply args to interpolate tasks.",
@@ -2019,6 +3456,17 @@ impl<'a> Runner<'a> {
                             .await?;
                     }
                 }
+                for env_file in self.tasks[task_id].env_files.clone() {
+                    if Path::new(&env_file).exists() {
+                        self.expand_target(watcher, &env_file, true, Some(job_num))
+                            .await?;
+                    } else if self.watch {
+                        // optional env files are watched through their folder until created
+                        if let Some(parent) = Path::new(&env_file).parent() {
+                            let _ = watcher.watch(parent, RecursiveMode::NonRecursive);
+                        }
+                    }
+                }
 
                 if let Some(target) = is_interpolate {
                     if !expanded_interpolate {
@@ -2245,7 +3693,11 @@ impl<'a> Runner<'a> {
         for &job_num in jobs {
             // if a job, make it live
             if let Some(ref mut job) = self.get_job_mut(job_num) {
-                job.live = true;
+                if !job.live {
+                    job.live = true;
+                    self.live_jobs += 1;
+                    self.critical_paths.clear();
+                }
             }
             self.drive_all(
                 job_num,
@@ -2260,7 +3712,14 @@ impl<'a> Runner<'a> {
             futures.push(Runner::watcher_interval().boxed_local());
         }
         while !futures.is_empty() {
-            let (transition, _idx, new_futures) = select_all(futures).await;
+            let mut next = select_all(futures);
+            let (transition, _idx, new_futures) = tokio::select! {
+                next = &mut next => next,
+                _ = interrupt_signal() => {
+                    interrupt_process_groups(join_all(next.into_inner())).await;
+                    break;
+                }
+            };
             futures = new_futures;
             match transition.state {
                 // Sentinel value used to enforce watcher task looping
@@ -2287,6 +3746,7 @@ impl<'a> Runner<'a> {
                             watch_listener.clone(),
                         )?;
                     }
+                    self.state.flush()?;
                     futures.push(Runner::watcher_interval().boxed_local());
                 }
                 _ => {
@@ -2297,8 +3757,17 @@ impl<'a> Runner<'a> {
                         &mut queued,
                         watch_listener.clone(),
                     )?;
+                    // the terminated tasks must exit before the run returns
+                    if self.cancelled {
+                        kill_process_groups(join_all(std::mem::take(&mut futures))).await;
+                        break;
+                    }
                 }
             }
+            // outside of watch mode, services are stopped once only they remain running
+            if !self.watch && !futures.is_empty() && futures.len() == self.running_services() {
+                self.stop_services();
+            }
         }
         Ok(())
     }
@@ -2337,6 +3806,7 @@ impl<'a> Runner<'a> {
                         if let Some(ref mut job) = self.get_job_mut(job_num) {
                             job.live = true;
                             redrives.insert(job_num);
+                            self.critical_paths.clear();
                         }
                     }
                 }
@@ -2353,6 +3823,11 @@ impl<'a> Runner<'a> {
             }
             Err(TryRecvError::Disconnected) => panic!("Watcher disconnected"),
         };
+        if self.reporter.is_json() {
+            self.reporter.report(Event::Watch {
+                path: &relative_path(&path.to_string_lossy().replace('\\', "/"), &self.cwd),
+            });
+        }
         let result = self.invalidate_path(&path, queued, redrives);
         watch_listener
             .send(path)
@@ -2379,6 +3854,16 @@ impl<'a> Runner<'a> {
             },
         )
         .unwrap();
+        self.dry_run = opts.dry_run || opts.explain;
+        self.explain = opts.explain;
+        self.reporter = opts.reporter;
+        self.keep_going = opts.keep_going;
+        self.fail_fast = opts.fail_fast;
+        self.cmd_pool.reporter = opts.reporter;
+        if opts.trace.is_some() {
+            self.cmd_pool.trace = Some(Trace::new());
+        }
+        self.cmd_pool.capture_stderr = opts.junit.is_some();
         let mut job_nums = HashSet::new();
         for target in opts.targets {
             let jobs = self
@@ -2396,6 +3881,10 @@ impl<'a> Runner<'a> {
         for &job_num in &job_nums {
             self.check_acyclic(job_num)?;
         }
+        if let Some(format) = opts.graph {
+            self.print_graph(&job_nums, format)?;
+            return Ok(true);
+        }
         // When running with arguments, mutate the task environment to include the arguments
         // Arguments tasks cannot be cached
         if let Some(args) = opts.args {
@@ -2434,15 +3923,52 @@ impl<'a> Runner<'a> {
             }
         }
 
-        self.drive_jobs(
-            debouncer.watcher(),
-            &job_nums,
-            opts.force,
-            rx,
-            watch_listener,
-            watch_writer,
-        )
-        .await?;
+        let run_start = Instant::now();
+        if opts.progress {
+            ui::start();
+        }
+        let result = self
+            .drive_jobs(
+                debouncer.watcher(),
+                &job_nums,
+                opts.force,
+                rx,
+                watch_listener,
+                watch_writer,
+            )
+            .await;
+        ui::stop();
+        // the reports are written even when the run errored, without one failing the other
+        let junit_result = match &opts.junit {
+            Some(path) => self
+                .write_junit(path)
+                .map_err(|e| anyhow!("Unable to write JUnit report {}: {}", path, e)),
+            None => Ok(()),
+        };
+        let trace_result = match (&opts.trace, &mut self.cmd_pool.trace) {
+            (Some(path), Some(trace)) => trace
+                .write(path)
+                .map_err(|e| anyhow!("Unable to write trace file {}: {}", path, e)),
+            _ => Ok(()),
+        };
+        result?;
+        if !self.dry_run {
+            self.state.flush()?;
+        }
+        if interrupted() {
+            junit_result?;
+            trace_result?;
+            return Ok(false);
+        }
+        if self.explain {
+            let mut visited = HashSet::new();
+            for &job_num in &job_nums {
+                self.explain_node(job_num, 0, None, &mut visited);
+            }
+        }
+        for upload in self.uploads.drain(..) {
+            let _ = upload.await;
+        }
         // if all jobs completed successfully, exit code is 0, otherwise its an error
         let mut all_ok = true;
         for &job_num in job_nums.iter() {
@@ -2452,6 +3978,14 @@ impl<'a> Runner<'a> {
                 break;
             }
         }
+        if !self.dry_run && (!all_ok || self.keep_going) {
+            self.print_summary();
+        }
+        if opts.timings && !self.dry_run {
+            self.print_timings(run_start.elapsed());
+        }
+        junit_result?;
+        trace_result?;
 
         Ok(all_ok)
     }
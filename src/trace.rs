@@ -0,0 +1,119 @@
+// Chomp Task Runner
+// Copyright (C) 2022  Guy Bedford
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+use std::time::Instant;
+
+// Chrome Trace Event Format, as loaded by Perfetto and chrome://tracing
+// https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU
+#[derive(Debug)]
+pub struct Trace {
+    start: Instant,
+    spans: Vec<Span>,
+}
+
+#[derive(Debug)]
+struct Span {
+    name: String,
+    start: Instant,
+    end: Instant,
+    args: SpanArgs,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SpanArgs {
+    pub exec: usize,
+    pub engine: String,
+    pub state: String,
+    pub jobs: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TraceFile<'a> {
+    trace_events: Vec<TraceEvent<'a>>,
+    display_time_unit: &'static str,
+}
+
+#[derive(Serialize)]
+struct TraceEvent<'a> {
+    name: &'a str,
+    cat: &'static str,
+    ph: &'static str,
+    ts: u128,
+    dur: u128,
+    pid: u32,
+    tid: usize,
+    args: &'a SpanArgs,
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        Trace {
+            start: Instant::now(),
+            spans: Vec::new(),
+        }
+    }
+
+    pub fn add_span(&mut self, name: String, start: Instant, args: SpanArgs) {
+        self.spans.push(Span {
+            name,
+            start,
+            end: Instant::now(),
+            args,
+        });
+    }
+
+    pub fn write(&mut self, path: &str) -> Result<()> {
+        self.spans.sort_by_key(|span| span.start);
+        // spans are laid out on the first free thread lane, so that concurrent
+        // execs are shown side by side instead of overlapping
+        let mut lanes: Vec<Instant> = Vec::new();
+        let mut trace_events = Vec::new();
+        for span in &self.spans {
+            let tid = match lanes.iter().position(|&end| end <= span.start) {
+                Some(lane) => {
+                    lanes[lane] = span.end;
+                    lane
+                }
+                None => {
+                    lanes.push(span.end);
+                    lanes.len() - 1
+                }
+            };
+            trace_events.push(TraceEvent {
+                name: &span.name,
+                cat: "exec",
+                ph: "X",
+                ts: (span.start - self.start).as_micros(),
+                dur: (span.end - span.start).as_micros(),
+                pid: 1,
+                tid,
+                args: &span.args,
+            });
+        }
+        fs::write(
+            path,
+            serde_json::to_string(&TraceFile {
+                trace_events,
+                display_time_unit: "ms",
+            })?,
+        )?;
+        Ok(())
+    }
+}
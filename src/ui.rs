@@ -0,0 +1,237 @@
+// Chomp Task Runner
+// Copyright (C) 2022  Guy Bedford
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use lazy_static::lazy_static;
+use std::io::{stderr, stdout, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+// running jobs beyond this are summarized in a single line
+const MAX_RUNNING_LINES: usize = 8;
+const DEFAULT_WIDTH: usize = 80;
+
+lazy_static! {
+    static ref PROGRESS: Mutex<Option<Progress>> = Mutex::new(None);
+}
+
+// with the JSON reporter stdout is reserved for the event stream, so that
+// status lines are written to stderr instead
+static STATUS_STDERR: AtomicBool = AtomicBool::new(false);
+
+pub fn status_to_stderr() {
+    STATUS_STDERR.store(true, Ordering::Relaxed);
+}
+
+// Live status lines of the running jobs, drawn below the regular output. While active,
+// all run output is written through this module so that the status lines can be cleared
+// before the output and redrawn after it.
+struct Progress {
+    running: Vec<(String, Instant)>,
+    completed: usize,
+    cached: usize,
+    total: usize,
+    // number of status lines currently drawn
+    drawn: usize,
+}
+
+impl Progress {
+    fn clear(&mut self, out: &mut impl Write) {
+        if self.drawn > 0 {
+            // move to the first status line and clear to the end of the screen
+            let _ = write!(out, "\x1b[{}F\x1b[J", self.drawn);
+            self.drawn = 0;
+        }
+    }
+
+    fn draw(&mut self, out: &mut impl Write) {
+        let width = terminal_width().saturating_sub(16).max(10);
+        let now = Instant::now();
+        let mut lines = Vec::new();
+        for (name, start) in self.running.iter().take(MAX_RUNNING_LINES) {
+            let name: String = name.chars().take(width).collect();
+            lines.push(format!(
+                "\x1b[1m▶ {}\x1b[0m \x1b[34m[{:.1}s]\x1b[0m",
+                name,
+                (now - *start).as_secs_f64()
+            ));
+        }
+        if self.running.len() > MAX_RUNNING_LINES {
+            lines.push(format!(
+                "  ...and {} more",
+                self.running.len() - MAX_RUNNING_LINES
+            ));
+        }
+        let mut status = format!(
+            "\x1b[1m{}/{}\x1b[0m jobs completed",
+            self.completed, self.total
+        );
+        if self.cached > 0 {
+            status.push_str(&format!(" \x1b[34m({} cached)\x1b[0m", self.cached));
+        }
+        lines.push(status);
+        for line in &lines {
+            let _ = writeln!(out, "{}", line);
+        }
+        let _ = out.flush();
+        self.drawn = lines.len();
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn terminal_width() -> usize {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+        && size.ws_col > 0
+    {
+        return size.ws_col as usize;
+    }
+    columns_env()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn terminal_width() -> usize {
+    columns_env()
+}
+
+fn columns_env() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+// the live progress display is only used when writing to a terminal
+pub fn supported() -> bool {
+    stdout().is_terminal() && std::env::var("TERM").map_or(true, |term| term != "dumb")
+}
+
+pub fn start() {
+    *PROGRESS.lock().unwrap() = Some(Progress {
+        running: Vec::new(),
+        completed: 0,
+        cached: 0,
+        total: 0,
+        drawn: 0,
+    });
+    // redraws the elapsed times of the running jobs
+    tokio::spawn(async {
+        loop {
+            sleep(REFRESH_INTERVAL).await;
+            let mut progress = PROGRESS.lock().unwrap();
+            let progress = match progress.as_mut() {
+                Some(progress) => progress,
+                None => break,
+            };
+            let mut out = stdout().lock();
+            progress.clear(&mut out);
+            progress.draw(&mut out);
+        }
+    });
+}
+
+// clears the status lines, leaving a single line for the collapsed cached jobs
+pub fn stop() {
+    let progress = PROGRESS.lock().unwrap().take();
+    if let Some(mut progress) = progress {
+        let mut out = stdout().lock();
+        progress.clear(&mut out);
+        if progress.cached > 0 {
+            let _ = writeln!(
+                out,
+                "\x1b[1m●\x1b[0m {} job{} \x1b[34m[cached]\x1b[0m",
+                progress.cached,
+                if progress.cached == 1 { "" } else { "s" }
+            );
+        }
+        let _ = out.flush();
+    }
+}
+
+pub fn is_active() -> bool {
+    PROGRESS.lock().unwrap().is_some()
+}
+
+pub fn job_started(name: &str) {
+    if let Some(progress) = PROGRESS.lock().unwrap().as_mut() {
+        // retried jobs restart their elapsed time
+        progress.running.retain(|(running, _)| running != name);
+        progress.running.push((name.to_string(), Instant::now()));
+    }
+}
+
+pub fn job_finished(name: &str, completed: usize, cached: usize, total: usize) {
+    let mut progress = PROGRESS.lock().unwrap();
+    if let Some(progress) = progress.as_mut() {
+        progress.running.retain(|(running, _)| running != name);
+        progress.completed = completed;
+        progress.cached = cached;
+        progress.total = total;
+        let mut out = stdout().lock();
+        progress.clear(&mut out);
+        progress.draw(&mut out);
+    }
+}
+
+pub fn println(line: &str) {
+    write_output(
+        STATUS_STDERR.load(Ordering::Relaxed),
+        format!("{}\n", line).as_bytes(),
+    );
+}
+
+pub fn eprintln(line: &str) {
+    write_output(true, format!("{}\n", line).as_bytes());
+}
+
+// writes run output above the status lines
+pub fn write_output(to_stderr: bool, output: &[u8]) {
+    write_group(&[(to_stderr, output.to_vec())]);
+}
+
+// writes a list of stdout and stderr outputs contiguously
+pub fn write_group(outputs: &[(bool, Vec<u8>)]) {
+    let mut progress = PROGRESS.lock().unwrap();
+    let mut out = stdout().lock();
+    if let Some(progress) = progress.as_mut() {
+        progress.clear(&mut out);
+    }
+    for (to_stderr, output) in outputs {
+        if *to_stderr {
+            let _ = out.flush();
+            let mut err = stderr().lock();
+            let _ = err.write_all(output);
+            // a partial last line would be overwritten by the status lines
+            if progress.is_some() && !output.ends_with(b"\n") {
+                let _ = err.write_all(b"\n");
+            }
+            let _ = err.flush();
+        } else {
+            let _ = out.write_all(output);
+            if progress.is_some() && !output.ends_with(b"\n") {
+                let _ = out.write_all(b"\n");
+            }
+        }
+    }
+    match progress.as_mut() {
+        Some(progress) => progress.draw(&mut out),
+        None => {
+            let _ = out.flush();
+        }
+    }
+}
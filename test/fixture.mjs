@@ -0,0 +1,44 @@
+// Helpers for the tests which run Chomp on a fixture project of their own
+import { spawn } from 'child_process';
+import { cpSync, existsSync, readFileSync, rmSync } from 'fs';
+import { fileURLToPath } from 'url';
+
+// the locally built Chomp binary, falling back to the one on the PATH
+const localChomp = fileURLToPath(new URL(`../target/debug/chomp${process.platform === 'win32' ? '.exe' : ''}`, import.meta.url));
+const chompBin = existsSync(localChomp) ? localChomp : 'chomp';
+
+// Copies fixtures/[name] into a fresh output/[dest] folder, returning its path
+export function fixture (name, dest = name) {
+  const dir = `output/${dest}`;
+  rmSync(dir, { recursive: true, force: true });
+  cpSync(`fixtures/${name}`, dir, { recursive: true });
+  return dir;
+}
+
+// Runs Chomp on the fixture folder with the given arguments and additional
+// environment variables, resolving with its exit code and output. Runs still
+// going after the timeout are killed, resolving with a null exit code.
+export function chomp (dir, args = [], env = {}, timeout = 30_000) {
+  return new Promise((resolve, reject) => {
+    const child = spawn(chompBin, ['-c', dir, ...args], { env: { ...process.env, ...env } });
+    const timer = setTimeout(() => child.kill('SIGKILL'), timeout);
+    let stdout = '', stderr = '';
+    child.stdout.on('data', data => stdout += data);
+    child.stderr.on('data', data => stderr += data);
+    child.on('error', reject);
+    child.on('close', code => {
+      clearTimeout(timer);
+      resolve({ code, stdout, stderr });
+    });
+  });
+}
+
+// Reads a text file written by a fixture task, without any BOM or trailing newline
+export function read (path) {
+  return readFileSync(path, 'utf8').replace(/^\uFEFF/, '').trim();
+}
+
+// Chomp output lines without their ANSI colours
+export function lines (output) {
+  return output.replace(/\x1b\[[0-9;]*m/g, '').split(/\r?\n/).map(line => line.trim()).filter(Boolean);
+}